@@ -0,0 +1,4 @@
+ALTER TABLE messages ADD COLUMN reaction_emojis TEXT;
+ALTER TABLE messages ADD COLUMN reaction_authors TEXT;
+
+DROP TABLE reactions;
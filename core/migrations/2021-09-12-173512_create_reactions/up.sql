@@ -0,0 +1,60 @@
+-- Reactions are keyed by the message they target and their author, the same
+-- way Signal identifies them. message_author is the sender of the target
+-- message, or empty if we sent it.
+CREATE TABLE reactions (
+  message_timestamp BIGINT NOT NULL,
+  message_author TEXT NOT NULL,
+  author TEXT NOT NULL,
+  emoji TEXT NOT NULL,
+  timestamp BIGINT NOT NULL,
+  PRIMARY KEY (message_timestamp, message_author, author)
+);
+
+-- Split the newline terminated emoji and author lists into rows
+WITH RECURSIVE split(message_timestamp, message_author, emojis, authors, emoji, author) AS (
+  SELECT timestamp,
+         CASE WHEN from_me THEN '' ELSE IFNULL(number, '') END,
+         reaction_emojis,
+         reaction_authors,
+         NULL,
+         NULL
+  FROM messages
+  WHERE reaction_emojis IS NOT NULL AND reaction_authors IS NOT NULL
+  UNION ALL
+  SELECT message_timestamp,
+         message_author,
+         substr(emojis, instr(emojis, char(10)) + 1),
+         substr(authors, instr(authors, char(10)) + 1),
+         substr(emojis, 1, instr(emojis, char(10)) - 1),
+         substr(authors, 1, instr(authors, char(10)) - 1)
+  FROM split
+  WHERE instr(emojis, char(10)) > 0 AND instr(authors, char(10)) > 0
+)
+INSERT OR REPLACE INTO reactions (message_timestamp, message_author, author, emoji, timestamp)
+SELECT message_timestamp, message_author, author, emoji, 0
+FROM split
+WHERE emoji IS NOT NULL;
+
+-- SQLite can't drop columns everywhere yet, so rebuild messages without them
+CREATE TABLE messages_new (
+  timestamp BIGINT NOT NULL,
+  number TEXT,
+  from_me TINYINT NOT NULL,
+  is_read TINYINT NOT NULL,
+  attachments TEXT,
+  body TEXT NOT NULL,
+  groupid TEXT,
+  quote_timestamp BIGINT,
+  quote_author TEXT,
+  mentions BLOB,
+  mentions_start BLOB,
+  PRIMARY KEY (timestamp, from_me, number, groupid)
+);
+
+INSERT INTO messages_new
+SELECT timestamp, number, from_me, is_read, attachments, body, groupid,
+       quote_timestamp, quote_author, mentions, mentions_start
+FROM messages;
+
+DROP TABLE messages;
+ALTER TABLE messages_new RENAME TO messages;
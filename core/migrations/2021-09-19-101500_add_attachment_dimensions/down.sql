@@ -0,0 +1,13 @@
+CREATE TABLE attachments_new (
+  id TEXT NOT NULL PRIMARY KEY,
+  blurhash TEXT,
+  content_type TEXT NOT NULL,
+  filename TEXT
+);
+
+INSERT INTO attachments_new
+SELECT id, blurhash, content_type, filename
+FROM attachments;
+
+DROP TABLE attachments;
+ALTER TABLE attachments_new RENAME TO attachments;
@@ -0,0 +1,5 @@
+-- conversation is the group id for groups, otherwise the contact's number
+CREATE TABLE conversation_settings (
+  conversation TEXT NOT NULL PRIMARY KEY,
+  muted TINYINT NOT NULL DEFAULT 0
+)
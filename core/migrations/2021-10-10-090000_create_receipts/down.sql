@@ -0,0 +1,25 @@
+DROP TABLE receipts;
+
+-- SQLite can't drop columns everywhere yet, so rebuild messages without it
+CREATE TABLE messages_new (
+  timestamp BIGINT NOT NULL,
+  number TEXT,
+  from_me TINYINT NOT NULL,
+  is_read TINYINT NOT NULL,
+  attachments TEXT,
+  body TEXT NOT NULL,
+  groupid TEXT,
+  quote_timestamp BIGINT,
+  quote_author TEXT,
+  mentions BLOB,
+  mentions_start BLOB,
+  PRIMARY KEY (timestamp, from_me, number, groupid)
+);
+
+INSERT INTO messages_new
+SELECT timestamp, number, from_me, is_read, attachments, body, groupid,
+       quote_timestamp, quote_author, mentions, mentions_start
+FROM messages;
+
+DROP TABLE messages;
+ALTER TABLE messages_new RENAME TO messages;
@@ -0,0 +1,14 @@
+-- How far our messages got: -1 failed, 0 sending, 1 sent, 2 delivered,
+-- 3 read. Messages from before receipts were tracked count as sent.
+ALTER TABLE messages ADD COLUMN status INTEGER NOT NULL DEFAULT 1;
+UPDATE messages SET status = 3 WHERE from_me AND is_read;
+
+-- The furthest each recipient got with one of our messages, which are
+-- identified by their timestamp alone
+CREATE TABLE receipts (
+  message_timestamp BIGINT NOT NULL,
+  member TEXT NOT NULL,
+  status INTEGER NOT NULL,
+  timestamp BIGINT NOT NULL,
+  PRIMARY KEY (message_timestamp, member)
+);
@@ -0,0 +1,6 @@
+-- Send requests for our messages that signald hasn't sent yet, as JSON,
+-- so they survive restarts and can be retried
+CREATE TABLE outbox (
+  timestamp BIGINT NOT NULL PRIMARY KEY,
+  request TEXT NOT NULL
+)
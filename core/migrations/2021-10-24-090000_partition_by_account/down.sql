@@ -0,0 +1,67 @@
+-- Messages of all accounts end up mixed together again
+CREATE TABLE messages_old (
+  timestamp BIGINT NOT NULL,
+  number TEXT,
+  from_me TINYINT NOT NULL,
+  is_read TINYINT NOT NULL,
+  attachments TEXT,
+  body TEXT NOT NULL,
+  groupid TEXT,
+  quote_timestamp BIGINT,
+  quote_author TEXT,
+  mentions BLOB,
+  mentions_start BLOB,
+  status INTEGER NOT NULL DEFAULT 1,
+  PRIMARY KEY (timestamp, from_me, number, groupid)
+);
+
+INSERT OR IGNORE INTO messages_old
+SELECT timestamp, number, from_me, is_read, attachments, body, groupid,
+       quote_timestamp, quote_author, mentions, mentions_start, status
+FROM messages;
+
+DROP TABLE messages;
+ALTER TABLE messages_old RENAME TO messages;
+
+CREATE TABLE reactions_old (
+  message_timestamp BIGINT NOT NULL,
+  message_author TEXT NOT NULL,
+  author TEXT NOT NULL,
+  emoji TEXT NOT NULL,
+  timestamp BIGINT NOT NULL,
+  PRIMARY KEY (message_timestamp, message_author, author)
+);
+
+INSERT OR IGNORE INTO reactions_old
+SELECT message_timestamp, message_author, author, emoji, timestamp
+FROM reactions;
+
+DROP TABLE reactions;
+ALTER TABLE reactions_old RENAME TO reactions;
+
+CREATE TABLE receipts_old (
+  message_timestamp BIGINT NOT NULL,
+  member TEXT NOT NULL,
+  status INTEGER NOT NULL,
+  timestamp BIGINT NOT NULL,
+  PRIMARY KEY (message_timestamp, member)
+);
+
+INSERT OR IGNORE INTO receipts_old
+SELECT message_timestamp, member, status, timestamp
+FROM receipts;
+
+DROP TABLE receipts;
+ALTER TABLE receipts_old RENAME TO receipts;
+
+CREATE TABLE outbox_old (
+  timestamp BIGINT NOT NULL PRIMARY KEY,
+  request TEXT NOT NULL
+);
+
+INSERT OR IGNORE INTO outbox_old
+SELECT timestamp, request
+FROM outbox;
+
+DROP TABLE outbox;
+ALTER TABLE outbox_old RENAME TO outbox;
@@ -0,0 +1,73 @@
+-- Everything about messages now belongs to one of our accounts. Rows from
+-- before are left with an empty account until the client adopts them.
+CREATE TABLE messages_new (
+  timestamp BIGINT NOT NULL,
+  number TEXT,
+  from_me TINYINT NOT NULL,
+  is_read TINYINT NOT NULL,
+  attachments TEXT,
+  body TEXT NOT NULL,
+  groupid TEXT,
+  quote_timestamp BIGINT,
+  quote_author TEXT,
+  mentions BLOB,
+  mentions_start BLOB,
+  status INTEGER NOT NULL DEFAULT 1,
+  account TEXT NOT NULL DEFAULT '',
+  PRIMARY KEY (account, timestamp, from_me, number, groupid)
+);
+
+INSERT INTO messages_new
+SELECT timestamp, number, from_me, is_read, attachments, body, groupid,
+       quote_timestamp, quote_author, mentions, mentions_start, status, ''
+FROM messages;
+
+DROP TABLE messages;
+ALTER TABLE messages_new RENAME TO messages;
+
+CREATE TABLE reactions_new (
+  message_timestamp BIGINT NOT NULL,
+  message_author TEXT NOT NULL,
+  author TEXT NOT NULL,
+  emoji TEXT NOT NULL,
+  timestamp BIGINT NOT NULL,
+  account TEXT NOT NULL DEFAULT '',
+  PRIMARY KEY (account, message_timestamp, message_author, author)
+);
+
+INSERT INTO reactions_new
+SELECT message_timestamp, message_author, author, emoji, timestamp, ''
+FROM reactions;
+
+DROP TABLE reactions;
+ALTER TABLE reactions_new RENAME TO reactions;
+
+CREATE TABLE receipts_new (
+  message_timestamp BIGINT NOT NULL,
+  member TEXT NOT NULL,
+  status INTEGER NOT NULL,
+  timestamp BIGINT NOT NULL,
+  account TEXT NOT NULL DEFAULT '',
+  PRIMARY KEY (account, message_timestamp, member)
+);
+
+INSERT INTO receipts_new
+SELECT message_timestamp, member, status, timestamp, ''
+FROM receipts;
+
+DROP TABLE receipts;
+ALTER TABLE receipts_new RENAME TO receipts;
+
+CREATE TABLE outbox_new (
+  timestamp BIGINT NOT NULL,
+  request TEXT NOT NULL,
+  account TEXT NOT NULL DEFAULT '',
+  PRIMARY KEY (account, timestamp)
+);
+
+INSERT INTO outbox_new
+SELECT timestamp, request, ''
+FROM outbox;
+
+DROP TABLE outbox;
+ALTER TABLE outbox_new RENAME TO outbox;
@@ -0,0 +1,12 @@
+-- Mutes of all accounts end up mixed together again
+CREATE TABLE conversation_settings_old (
+  conversation TEXT NOT NULL PRIMARY KEY,
+  muted TINYINT NOT NULL DEFAULT 0
+);
+
+INSERT OR IGNORE INTO conversation_settings_old
+SELECT conversation, muted
+FROM conversation_settings;
+
+DROP TABLE conversation_settings;
+ALTER TABLE conversation_settings_old RENAME TO conversation_settings;
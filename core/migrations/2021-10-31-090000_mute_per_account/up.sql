@@ -0,0 +1,15 @@
+-- Muting belongs to one of our accounts too. Rows from before are left with
+-- an empty account until the client adopts them.
+CREATE TABLE conversation_settings_new (
+  conversation TEXT NOT NULL,
+  muted TINYINT NOT NULL DEFAULT 0,
+  account TEXT NOT NULL DEFAULT '',
+  PRIMARY KEY (account, conversation)
+);
+
+INSERT INTO conversation_settings_new
+SELECT conversation, muted, ''
+FROM conversation_settings;
+
+DROP TABLE conversation_settings;
+ALTER TABLE conversation_settings_new RENAME TO conversation_settings;
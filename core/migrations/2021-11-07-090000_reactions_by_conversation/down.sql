@@ -0,0 +1,18 @@
+-- Reactions to our own messages in different conversations end up mixed
+-- together again
+CREATE TABLE reactions_old (
+  message_timestamp BIGINT NOT NULL,
+  message_author TEXT NOT NULL,
+  author TEXT NOT NULL,
+  emoji TEXT NOT NULL,
+  timestamp BIGINT NOT NULL,
+  account TEXT NOT NULL DEFAULT '',
+  PRIMARY KEY (account, message_timestamp, message_author, author)
+);
+
+INSERT OR IGNORE INTO reactions_old
+SELECT message_timestamp, message_author, author, emoji, timestamp, account
+FROM reactions;
+
+DROP TABLE reactions;
+ALTER TABLE reactions_old RENAME TO reactions;
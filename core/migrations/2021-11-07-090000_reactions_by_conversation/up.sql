@@ -0,0 +1,28 @@
+-- Our own messages are all keyed by an empty author, so tell them apart by
+-- conversation, the group id for groups and otherwise the contact's number
+CREATE TABLE reactions_new (
+  message_timestamp BIGINT NOT NULL,
+  message_author TEXT NOT NULL,
+  author TEXT NOT NULL,
+  emoji TEXT NOT NULL,
+  timestamp BIGINT NOT NULL,
+  account TEXT NOT NULL DEFAULT '',
+  conversation TEXT NOT NULL DEFAULT '',
+  PRIMARY KEY (account, conversation, message_timestamp, message_author, author)
+);
+
+INSERT OR IGNORE INTO reactions_new
+SELECT message_timestamp, message_author, author, emoji, reactions.timestamp, account,
+       COALESCE((
+         SELECT COALESCE(messages.groupid, messages.number)
+         FROM messages
+         WHERE messages.account = reactions.account
+           AND messages.timestamp = reactions.message_timestamp
+           AND ((reactions.message_author = '' AND messages.from_me = 1)
+             OR (messages.from_me = 0 AND messages.number = reactions.message_author))
+         LIMIT 1
+       ), '')
+FROM reactions;
+
+DROP TABLE reactions;
+ALTER TABLE reactions_new RENAME TO reactions;
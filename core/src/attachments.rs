@@ -0,0 +1,98 @@
+//! Finding the files behind stored attachments. signald records where it
+//! saved each download, but that path goes stale if its data directory
+//! moves, so known data directories are searched as well.
+
+use dotenv::dotenv;
+use std::env;
+use std::path::{Path, PathBuf};
+
+use crate::models::Attachment;
+use crate::signald_bridge::expand_env_vars;
+
+const DEFAULT_DATA_DIRS: [&str; 4] = [
+    "$XDG_DATA_HOME/signald",
+    "$HOME/.local/share/signald",
+    "$HOME/.config/signald",
+    "/var/lib/signald"
+];
+
+pub struct AttachmentStore {
+    data_dirs: Vec<PathBuf>
+}
+
+impl AttachmentStore {
+    pub fn new(data_dirs: Vec<PathBuf>) -> Self {
+        AttachmentStore {
+            data_dirs
+        }
+    }
+
+    /// Use the directory set by `SIGNALD_DATA_DIR` if present, otherwise
+    /// signald's default locations
+    pub fn from_env() -> Self {
+        dotenv().ok();
+
+        let data_dirs = match env::var("SIGNALD_DATA_DIR") {
+            Ok(dir) => expand_env_vars(&dir).into_iter().map(PathBuf::from).collect(),
+            Err(_) => DEFAULT_DATA_DIRS.iter().filter_map(|dir| {
+                expand_env_vars(dir)
+            }).map(PathBuf::from).collect()
+        };
+
+        AttachmentStore::new(data_dirs)
+    }
+
+    /// The file holding `attachment`, or `None` if it's missing
+    pub fn resolve(&self, attachment: &Attachment) -> Option<PathBuf> {
+        if let Some(filename) = &attachment.filename {
+            let stored = Path::new(filename);
+            if stored.is_file() {
+                return Some(stored.to_owned());
+            }
+        }
+
+        // signald names downloads after the attachment id
+        let name = attachment.filename.as_ref()
+            .and_then(|filename| Path::new(filename).file_name())
+            .and_then(|name| name.to_str())
+            .unwrap_or(&attachment.id);
+
+        self.data_dirs.iter().map(|dir| {
+            dir.join("attachments").join(name)
+        }).find(|path| path.is_file())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attachment(id: &str, filename: Option<&str>) -> Attachment {
+        Attachment {
+            id: id.to_owned(),
+            blurhash: None,
+            content_type: "image/png".to_owned(),
+            filename: filename.map(|filename| filename.to_owned()),
+            width: None,
+            height: None
+        }
+    }
+
+    #[test]
+    fn resolves_attachment_paths() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let attachments = data_dir.path().join("attachments");
+        std::fs::create_dir(&attachments).unwrap();
+        std::fs::write(attachments.join("1234"), b"").unwrap();
+        std::fs::write(attachments.join("5678"), b"").unwrap();
+
+        let store = AttachmentStore::new(vec![PathBuf::from("/nonexistent"), data_dir.path().to_owned()]);
+
+        let stored = attachments.join("1234");
+        assert_eq!(store.resolve(&attachment("1234", stored.to_str())), Some(stored.clone()));
+        // signald's data directory moved since the attachment was stored
+        assert_eq!(store.resolve(&attachment("1234", Some("/old/signald/attachments/1234"))), Some(stored));
+        assert_eq!(store.resolve(&attachment("5678", None)), Some(attachments.join("5678")));
+        assert_eq!(store.resolve(&attachment("9999", None)), None);
+    }
+}
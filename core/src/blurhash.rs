@@ -0,0 +1,117 @@
+//! Decoding BlurHash placeholders (https://blurha.sh), the blurry preview
+//! signald stores for images and videos before they're downloaded
+
+const CHARACTERS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+/// Decode `hash` into `width` x `height` RGBA pixels, or `None` if it
+/// isn't a valid BlurHash
+pub fn decode(hash: &str, width: u32, height: u32) -> Option<Vec<u8>> {
+    let hash = hash.as_bytes();
+    if hash.len() < 6 {
+        return None;
+    }
+
+    let size_flag = decode_base83(&hash[0..1])?;
+    let num_x = (size_flag % 9 + 1) as usize;
+    let num_y = (size_flag / 9 + 1) as usize;
+    if hash.len() != 4 + 2 * num_x * num_y {
+        return None;
+    }
+
+    let max_value = (decode_base83(&hash[1..2])? + 1) as f32 / 166.0;
+
+    let mut colors = Vec::with_capacity(num_x * num_y);
+    colors.push(decode_dc(decode_base83(&hash[2..6])?));
+    for component in 1..num_x * num_y {
+        let start = 4 + component * 2;
+        colors.push(decode_ac(decode_base83(&hash[start..start + 2])?, max_value));
+    }
+
+    let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let mut pixel = [0.0; 3];
+
+            for j in 0..num_y {
+                for i in 0..num_x {
+                    let basis = (std::f32::consts::PI * x as f32 * i as f32 / width as f32).cos()
+                        * (std::f32::consts::PI * y as f32 * j as f32 / height as f32).cos();
+                    let color = colors[i + j * num_x];
+
+                    for (value, component) in pixel.iter_mut().zip(color.iter()) {
+                        *value += component * basis;
+                    }
+                }
+            }
+
+            pixels.extend(pixel.iter().map(|&value| linear_to_srgb(value)));
+            pixels.push(255);
+        }
+    }
+
+    Some(pixels)
+}
+
+fn decode_base83(chars: &[u8]) -> Option<u32> {
+    chars.iter().try_fold(0, |value, c| {
+        CHARACTERS.iter().position(|character| character == c).map(|digit| {
+            value * 83 + digit as u32
+        })
+    })
+}
+
+fn decode_dc(value: u32) -> [f32; 3] {
+    [
+        srgb_to_linear(value >> 16),
+        srgb_to_linear((value >> 8) & 255),
+        srgb_to_linear(value & 255)
+    ]
+}
+
+fn decode_ac(value: u32, max_value: f32) -> [f32; 3] {
+    let quantised = [value / (19 * 19), (value / 19) % 19, value % 19];
+
+    let mut color = [0.0; 3];
+    for (channel, quantised) in quantised.iter().enumerate() {
+        let normalised = (*quantised as f32 - 9.0) / 9.0;
+        color[channel] = normalised.signum() * normalised.powi(2) * max_value;
+    }
+
+    color
+}
+
+fn srgb_to_linear(value: u32) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+    if value <= 0.003_130_8 {
+        (value * 12.92 * 255.0 + 0.5) as u8
+    } else {
+        ((1.055 * value.powf(1.0 / 2.4) - 0.055) * 255.0 + 0.5) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_blurhashes() {
+        // A single red component decodes to a solid colour
+        let pixels = decode("00TI:j", 2, 2).unwrap();
+        assert_eq!(pixels, [255, 0, 0, 255].repeat(4));
+
+        let pixels = decode("LEHV6nWB2yk8pyo0adR*.7kCMdnj", 32, 24).unwrap();
+        assert_eq!(pixels.len(), 32 * 24 * 4);
+
+        assert!(decode("LEHV6nWB2yk8", 32, 24).is_none());
+        assert!(decode("00TI:\"", 2, 2).is_none());
+    }
+}
@@ -0,0 +1,1487 @@
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::path::PathBuf;
+use std::time::Duration;
+use signald::types::{AccountV1, AddLinkedDeviceRequestV1, DeviceInfoV1, GetLinkedDevicesRequestV1, JsonAddressV1,
+                     JsonAttachmentV0, JsonQuoteV1, JsonReactionV1, ListAccountsRequestV1,
+                     ListContactsRequestV1, ListGroupsRequestV1, MarkReadRequestV1,
+                     ProfileV1, ReactRequestV1, RegisterRequestV1, RemoveLinkedDeviceRequestV1, RequestSyncRequestV1,
+                     SendRequestV1, SendResponse, SignaldTypes, SubscribeRequestV1, TypingRequestV1, VerifyRequestV1};
+use diesel::sqlite::SqliteConnection;
+use uuid::Uuid;
+
+use async_std::channel::{bounded, Sender, Receiver};
+
+use crate::attachments::AttachmentStore;
+use crate::conversation::{Conversation, ConversationType};
+use crate::database;
+use crate::error::{Error, Result};
+use crate::models::{Attachment, Message, MessageStatus, NewMessage};
+use crate::settings::{ACTIVE_ACCOUNT, SEND_TYPING};
+use crate::notifications::{ConnectionState, Notification, Reaction};
+use crate::signald_bridge::{listen, BridgeConfig, PendingRequest, SignaldInteraction, Timeouts};
+use crate::signal_type_utils::*;
+
+type ContactMap = HashMap<String, ProfileV1>;
+
+/// The device an account was registered on, the only one that can link
+/// and unlink others
+pub const PRIMARY_DEVICE_ID: i64 = 1;
+
+/// A file on disk to send along with a message
+#[derive(Clone)]
+pub struct OutgoingAttachment {
+    pub path: String,
+    pub content_type: String
+}
+
+pub enum Event {
+    /// `conversation` is `None` if the message doesn't belong to any known
+    /// conversation, or was sent to an account other than the active one
+    NewMessage {
+        conversation: Option<Rc<Conversation>>,
+        message: NewMessage
+    },
+    Reaction(Reaction),
+    /// Who's typing in `conversation` changed, see `Conversation::typists`
+    Typing {
+        conversation: Option<Rc<Conversation>>
+    },
+    /// Our messages whose status a delivery or read receipt moved forward
+    Receipt(Vec<Message>),
+    /// Unread messages in `conversations` were read on another device
+    Read {
+        conversations: Vec<Rc<Conversation>>
+    },
+    Connection(ConnectionState)
+}
+
+/// Owns the signald bridge, database and conversation state. Messages
+/// arrive for every account, conversations are those of the active one.
+pub struct Client {
+    account: RefCell<String>,
+    accounts: RefCell<Vec<String>>,
+    signald_sender: Sender<SignaldInteraction>,
+    notification_receiver: Receiver<Notification>,
+    timeouts: RefCell<Timeouts>,
+    conversations: RefCell<Vec<Rc<Conversation>>>,
+    contacts: RefCell<ContactMap>,
+    attachments: AttachmentStore,
+    // Our messages signald is sending right now, by account and timestamp
+    in_flight: RefCell<HashSet<(String, i64)>>,
+    // The timestamp given to the last message we composed
+    last_timestamp: Cell<i64>,
+    db: Arc<Mutex<SqliteConnection>>
+}
+
+impl Client {
+    /// Connect using the socket and database configured in the environment
+    pub fn from_env() -> Rc<Self> {
+        Client::new(BridgeConfig::from_env(), database::establish_connection())
+    }
+
+    pub fn new(config: BridgeConfig, db: SqliteConnection) -> Rc<Self> {
+        let (msg_sender, msg_receiver) = bounded(10);
+        let (notification_sender, notification_receiver) = bounded(10);
+        let db = Arc::new(Mutex::new(db));
+
+        // The bridge lives on the async std runtime so it keeps receiving
+        // no matter what the frontend's main loop is doing
+        async_std::task::spawn(listen(config, db.clone(), msg_receiver, notification_sender));
+
+        Rc::new(Client {
+            account: RefCell::new(String::new()),
+            accounts: RefCell::new(Vec::new()),
+            signald_sender: msg_sender,
+            notification_receiver,
+            timeouts: RefCell::new(Timeouts::from_env()),
+            conversations: RefCell::new(Vec::new()),
+            contacts: RefCell::new(HashMap::new()),
+            attachments: AttachmentStore::from_env(),
+            in_flight: RefCell::new(HashSet::new()),
+            last_timestamp: Cell::new(0),
+            db
+        })
+    }
+
+    /// The active account
+    pub fn account(&self) -> String {
+        self.account.borrow().clone()
+    }
+
+    /// Every account we receive messages for
+    pub fn accounts(&self) -> Vec<String> {
+        self.accounts.borrow().clone()
+    }
+
+    /// Receive messages for `account` too, e.g. once it's been linked
+    pub fn add_account(&self, account: &str) {
+        let mut accounts = self.accounts.borrow_mut();
+        if !accounts.iter().any(|known| known == account) {
+            accounts.push(account.to_owned());
+        }
+    }
+
+    /// The account that was active last time, if it's still around
+    pub fn last_account(&self) -> Option<String> {
+        database::get_setting(&self.db(), ACTIVE_ACCOUNT).filter(|account| {
+            self.accounts.borrow().contains(account)
+        })
+    }
+
+    /// How many messages `account` has waiting, across its conversations
+    pub fn unread_count(&self, account: &str) -> usize {
+        if account == *self.account.borrow() {
+            self.conversations.borrow().iter().map(|conversation| {
+                *conversation.new_msgs.borrow()
+            }).sum()
+        } else {
+            database::count_unread(&self.db(), account)
+        }
+    }
+
+    /// Where `attachment` is on disk, `None` if it's missing
+    pub fn attachment_path(&self, attachment: &Attachment) -> Option<PathBuf> {
+        self.attachments.resolve(attachment)
+    }
+
+    pub fn db(&self) -> MutexGuard<'_, SqliteConnection> {
+        self.db.lock().unwrap()
+    }
+
+    /// Conversations ordered by most recent message
+    pub fn conversations(&self) -> Vec<Rc<Conversation>> {
+        self.conversations.borrow().clone()
+    }
+
+    pub fn find_conversation(&self, number: &Option<String>, groupid: &Option<String>) -> Option<Rc<Conversation>> {
+        self.conversations.borrow().iter().find(|conversation| {
+            conversation.contains(number, groupid)
+        }).cloned()
+    }
+
+    /// The conversation identified by `key`, see `Conversation::key`
+    pub fn conversation(&self, key: &str) -> Option<Rc<Conversation>> {
+        self.conversations.borrow().iter().find(|conversation| {
+            conversation.key() == key
+        }).cloned()
+    }
+
+    /// A yes/no preference, see `settings` for the keys
+    pub fn flag(&self, key: &str, default: bool) -> bool {
+        database::get_setting(&self.db(), key)
+            .map(|value| value == "true")
+            .unwrap_or(default)
+    }
+
+    pub fn set_flag(&self, key: &str, enabled: bool) {
+        database::set_setting(&self.db(), key, if enabled { "true" } else { "false" });
+    }
+
+    /// Whether `account` muted the conversation with `key`, also for
+    /// accounts whose conversations aren't loaded
+    pub fn is_muted(&self, account: &str, key: &str) -> bool {
+        database::is_muted(&self.db(), account, key)
+    }
+
+    /// Mute or unmute notifications for `conversation`
+    pub fn set_muted(&self, conversation: &Conversation, muted: bool) {
+        database::set_muted(&self.db(), &conversation.account, conversation.key(), muted);
+        conversation.muted.replace(muted);
+    }
+
+    pub fn get_name(&self, number: &str) -> Option<String> {
+        (*self.contacts.borrow()).get(number).as_ref().map(|profile| {
+            profile.get_name()
+        })
+    }
+
+    pub async fn dispatch(&self, key: &'static str, msg: SignaldTypes) -> Result<SignaldTypes> {
+        self.request(key, msg).await?.response().await
+    }
+
+    /// Hand a request to the bridge without waiting for the response, so
+    /// it can be cancelled through `PendingRequest::cancel_handle`
+    pub async fn request(&self, key: &'static str, msg: SignaldTypes) -> Result<PendingRequest> {
+        let timeout = self.timeouts.borrow().get(key);
+        let (request, sender) = PendingRequest::new(key, timeout);
+
+        self.signald_sender.send(
+            SignaldInteraction {
+                key,
+                msg,
+                timeout,
+                response_channel: Some(sender)
+            }
+        ).await.map_err(|_| Error::Socket("signald bridge stopped".to_owned()))?;
+
+        Ok(request)
+    }
+
+    pub fn set_timeout(&self, key: &str, timeout: Duration) {
+        self.timeouts.borrow_mut().set(key, timeout);
+    }
+
+    pub async fn list_accounts(&self) -> Result<Vec<AccountV1>> {
+        let account_list = self.dispatch(
+            "list_accounts",
+            SignaldTypes::ListAccountsRequestV1(
+                ListAccountsRequestV1::default()
+            )
+        ).await?;
+
+        if let SignaldTypes::AccountListV1(account_list) = account_list {
+            Ok(account_list.accounts.unwrap_or_default())
+        } else {
+            Err(Error::unexpected("list_accounts"))
+        }
+    }
+
+    /// Find the accounts signald has, see `accounts`. Returns their numbers.
+    pub async fn load_accounts(&self) -> Result<Vec<String>> {
+        let accounts: Vec<String> = self.list_accounts().await?
+            .drain(..)
+            .filter_map(|account| account.account_id)
+            .collect();
+
+        // Messages from when only one account was supported belong to
+        // the one that was used then
+        if let Some(account) = accounts.last() {
+            database::adopt_unowned(&self.db(), account);
+        }
+
+        *self.accounts.borrow_mut() = accounts.clone();
+        Ok(accounts)
+    }
+
+    /// Start registering `number`, in E.164, by having Signal text it a
+    /// verification code or call with it if `voice` is set. Fails with
+    /// `Error::CaptchaRequired` until a captcha token is passed.
+    pub async fn register(&self, number: &str, voice: bool, captcha: Option<String>) -> Result<()> {
+        self.dispatch(
+            "register",
+            SignaldTypes::RegisterRequestV1(
+                RegisterRequestV1 {
+                    account: Some(number.to_owned()),
+                    voice: Some(voice),
+                    captcha,
+                    server: None
+                }
+            )
+        ).await?;
+
+        Ok(())
+    }
+
+    /// Finish registering `number` with the code Signal sent. Numbers with
+    /// a registration lock fail with `Error::RegistrationLocked` until the
+    /// PIN is passed. Returns the new account.
+    pub async fn verify(&self, number: &str, code: &str, pin: Option<String>) -> Result<String> {
+        // Codes are shown as 123-456
+        let code: String = code.chars().filter(|c| c.is_ascii_digit()).collect();
+
+        let account = self.dispatch(
+            "verify",
+            SignaldTypes::VerifyRequestV1(
+                VerifyRequestV1 {
+                    account: Some(number.to_owned()),
+                    code: Some(code),
+                    pin
+                }
+            )
+        ).await?;
+
+        match account {
+            SignaldTypes::AccountV1(account) => Ok(account.account_id.unwrap_or_else(|| number.to_owned())),
+            _ => Err(Error::unexpected("verify"))
+        }
+    }
+
+    /// Which of its devices the active account is using here
+    pub async fn device_id(&self) -> Result<Option<i64>> {
+        let account = self.account();
+
+        Ok(self.list_accounts().await?.drain(..)
+            .find(|listed| listed.account_id.as_ref() == Some(&account))
+            .and_then(|listed| listed.device_id))
+    }
+
+    /// Every device of the active account, this one included
+    pub async fn linked_devices(&self) -> Result<Vec<DeviceInfoV1>> {
+        let devices = self.dispatch(
+            "get_linked_devices",
+            SignaldTypes::GetLinkedDevicesRequestV1(
+                GetLinkedDevicesRequestV1 {
+                    account: Some(self.account())
+                }
+            )
+        ).await?;
+
+        if let SignaldTypes::LinkedDevicesV1(devices) = devices {
+            Ok(devices.devices.unwrap_or_default())
+        } else {
+            Err(Error::unexpected("get_linked_devices"))
+        }
+    }
+
+    /// Remove a device from the active account, it stops receiving
+    /// messages straight away
+    pub async fn unlink_device(&self, device_id: i64) -> Result<()> {
+        self.dispatch(
+            "remove_linked_device",
+            SignaldTypes::RemoveLinkedDeviceRequestV1(
+                RemoveLinkedDeviceRequestV1 {
+                    account: Some(self.account()),
+                    device_id: Some(device_id)
+                }
+            )
+        ).await?;
+
+        Ok(())
+    }
+
+    /// Link the device showing `uri` as a QR code to the active account.
+    /// Only works from the primary device.
+    pub async fn add_device(&self, uri: &str) -> Result<()> {
+        let uri = uri.trim();
+        if !is_linking_uri(uri) {
+            return Err(Error::InvalidLinkingUri(uri.to_owned()));
+        }
+
+        self.dispatch(
+            "add_device",
+            SignaldTypes::AddLinkedDeviceRequestV1(
+                AddLinkedDeviceRequestV1 {
+                    account: Some(self.account()),
+                    uri: Some(uri.to_owned())
+                }
+            )
+        ).await?;
+
+        Ok(())
+    }
+
+    /// Make `account` the active account and load its conversations
+    pub async fn load_account(&self, account: String) -> Result<()> {
+        self.add_account(&account);
+
+        let conversations = self.get_conversations(&account).await?;
+        *self.account.borrow_mut() = account.clone();
+        *self.conversations.borrow_mut() = conversations;
+        self.order_conversations();
+        database::set_setting(&self.db(), ACTIVE_ACCOUNT, &account);
+
+        Ok(())
+    }
+
+    /// Start receiving messages for every account, see `subscribe_account`
+    pub async fn subscribe(&self) -> Result<()> {
+        for account in self.accounts() {
+            self.subscribe_account(&account).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Start receiving messages for `account` and ask its linked devices
+    /// to sync
+    pub async fn subscribe_account(&self, account: &str) -> Result<()> {
+        self.dispatch(
+            "subscribe",
+            SignaldTypes::SubscribeRequestV1(
+                SubscribeRequestV1 {
+                    account: Some(account.to_owned())
+                }
+            )
+        ).await?;
+
+        self.dispatch(
+            "request_sync",
+            SignaldTypes::RequestSyncRequestV1(
+                RequestSyncRequestV1 {
+                    account: Some(account.to_owned()),
+                    groups: Some(true),
+                    configuration: Some(true),
+                    contacts: Some(true),
+                    blocked: Some(true)
+                }
+            )
+        ).await?;
+
+        Ok(())
+    }
+
+    /// Wait for the next thing that happened on signald's side, updating
+    /// conversation state to match before handing it over. Only new
+    /// messages are passed on for accounts other than the active one.
+    pub async fn next_event(&self) -> Event {
+        loop {
+            let notification = self.notification_receiver.recv()
+                .await
+                .expect("Failed to receive notification");
+
+            if let Some(event) = self.handle_notification(notification) {
+                return event;
+            }
+        }
+    }
+
+    fn handle_notification(&self, notification: Notification) -> Option<Event> {
+        let active = self.account();
+
+        let event = match notification {
+            Notification::NewMessage(message) => {
+                let conversation = if message.account == active {
+                    self.find_conversation(&message.number, &message.groupid)
+                } else {
+                    None
+                };
+                if let Some(conversation) = &conversation {
+                    conversation.notify_msg(&message);
+                }
+                self.order_conversations();
+
+                Event::NewMessage {
+                    conversation,
+                    message
+                }
+            },
+            Notification::Reaction(reaction) if reaction.account == active => Event::Reaction(reaction),
+            Notification::Typing(typing) if typing.account == active => {
+                let number = Some(typing.number.clone());
+                let conversation = self.find_conversation(&number, &typing.groupid);
+                if let Some(conversation) = &conversation {
+                    conversation.set_typing(&typing.number, typing.started);
+                }
+
+                Event::Typing {
+                    conversation
+                }
+            },
+            Notification::Receipt(mut messages) => {
+                messages.retain(|msg| msg.account == active);
+                if messages.is_empty() {
+                    return None;
+                }
+
+                Event::Receipt(messages)
+            },
+            Notification::Read { account, read } if account == active => {
+                let conversations = self.conversations.borrow().iter()
+                    .filter(|conversation| conversation.read_elsewhere(&read))
+                    .cloned()
+                    .collect();
+
+                Event::Read {
+                    conversations
+                }
+            },
+            Notification::Connection(state) => Event::Connection(state),
+            // Already stored, nothing to show for an inactive account
+            Notification::Reaction(_) | Notification::Typing(_) | Notification::Read { .. } => return None
+        };
+
+        Some(event)
+    }
+
+    pub fn order_conversations(&self) {
+        let db = self.db();
+        self.conversations.borrow().iter().for_each(|conv| {
+            conv.set_last_message(&db);
+        });
+
+        self.conversations.borrow_mut().sort_by(|c1, c2| {
+            c2.last_message_time.borrow().cmp(
+                &c1.last_message_time.borrow()
+            )
+        });
+    }
+
+    /// Mark everything unread in `conversation` as read, locally and for
+    /// the senders
+    pub async fn mark_read(&self, conversation: &Conversation) -> Result<()> {
+        conversation.new_msgs.replace(0);
+        let unread: Vec<(String, Vec<i64>)> = conversation.unread.borrow_mut().drain().collect();
+
+        let mut result = Ok(());
+        for (number, timestamps) in unread {
+            database::read_msgs(&self.db(), &conversation.account, &timestamps, &number);
+            let response = self.dispatch(
+                "mark_read",
+                SignaldTypes::MarkReadRequestV1(
+                    MarkReadRequestV1 {
+                        account: Some(conversation.account.clone()),
+                        timestamps: Some(timestamps),
+                        to: JsonAddressV1::from_number(number),
+                        when: Some(chrono::offset::Local::now().timestamp_millis())
+                    }
+                )
+            ).await;
+
+            // Keep going so one failure doesn't leave the rest unsent
+            if let Err(e) = response {
+                result = Err(e);
+            }
+        }
+
+        result
+    }
+
+    /// Tell `conversation` we started or stopped typing, unless typing
+    /// indicators are turned off
+    pub async fn typing(&self, conversation: &Conversation, typing: bool) -> Result<()> {
+        if !self.flag(SEND_TYPING, true) {
+            return Ok(());
+        }
+
+        let typing = construct_typing(&self.account(), conversation, typing);
+        self.dispatch(
+            "typing",
+            SignaldTypes::TypingRequestV1(
+                typing
+            )
+        ).await?;
+
+        Ok(())
+    }
+
+    /// A request sending `body` to `conversation` from the active account,
+    /// quoting `reply_to` if it's a reply
+    pub fn compose(&self, conversation: &Conversation, body: String, reply_to: Option<&Message>, attachments: &[OutgoingAttachment]) -> Result<SendRequestV1> {
+        let account = self.account();
+        let quote = reply_to.map(|msg| construct_quote(&account, msg));
+        let attachments = if attachments.is_empty() {
+            None
+        } else {
+            Some(attachments.iter().map(construct_attachment).collect::<Result<_>>()?)
+        };
+
+        Ok(construct_message(&account, conversation, body, quote, attachments, self.next_timestamp()))
+    }
+
+    // Our messages are keyed by their millisecond timestamp, so ones sent
+    // in quick succession mustn't share one
+    fn next_timestamp(&self) -> i64 {
+        let now = chrono::offset::Local::now().timestamp_millis();
+        let timestamp = now.max(self.last_timestamp.get() + 1);
+        self.last_timestamp.set(timestamp);
+
+        timestamp
+    }
+
+    /// Store a message we're about to send, queueing it in the outbox
+    /// until signald has sent it
+    pub fn store_outgoing(&self, msg: &SendRequestV1) -> NewMessage {
+        let db = self.db();
+        let request = serde_json::to_string(msg).expect("Failed to serialize send request");
+        let (mentions, mentions_start) = database::convert_mentions(&msg.mentions);
+        let msg = NewMessage {
+            timestamp: msg.timestamp.unwrap(),
+            number: msg.recipient_address.as_ref().map(|address| {
+                address.number.unwrap_clone()
+            }),
+            from_me: true,
+            is_read: false,
+            attachments: database::store_attachments(&db, msg.attachments.as_ref()),
+            body: msg.message_body.unwrap_clone(),
+            groupid: msg.recipient_group_id.as_ref().map(|id| id.clone()),
+            quote_timestamp: msg.quote.as_ref().map(|quote| quote.id.unwrap()),
+            quote_author: msg.quote.as_ref().map(|quote| {
+                quote.author.get_number()
+            }),
+            mentions,
+            mentions_start,
+            status: MessageStatus::Sending as i32,
+            account: msg.username.unwrap_clone()
+        };
+
+        database::store_message(&db, &msg);
+        database::queue_outgoing(&db, &msg.account, msg.timestamp, &request);
+
+        msg
+    }
+
+    /// Send a message stored with `store_outgoing`. If signald can't be
+    /// reached or doesn't answer in time it stays queued for
+    /// `flush_outbox`, if it couldn't be sent to anyone it's marked failed. Either way, if anyone missed out it
+    /// stays around for `retry`.
+    pub async fn send(&self, msg: SendRequestV1) -> Result<()> {
+        let timestamp = msg.timestamp.unwrap();
+        let account = msg.username.unwrap_clone();
+
+        // Already on its way, e.g. sent while the outbox was flushed
+        if !self.in_flight.borrow_mut().insert((account.clone(), timestamp)) {
+            return Ok(());
+        }
+
+        let response = self.dispatch(
+            "send",
+            SignaldTypes::SendRequestV1(msg)
+        ).await;
+        self.in_flight.borrow_mut().remove(&(account.clone(), timestamp));
+
+        let mut missed = false;
+        let result = match response {
+            Ok(SignaldTypes::SendResponse(response)) => {
+                let results = send_results(&response);
+                let when = chrono::offset::Local::now().timestamp_millis();
+                database::store_send_results(&self.db(), &account, timestamp, &results, when);
+
+                missed = results.iter().any(|(_, sent)| !sent);
+                check_sent(&response)
+            },
+            Ok(_) => Err(Error::unexpected("send")),
+            Err(e) => Err(e)
+        };
+
+        let db = self.db();
+        match &result {
+            Ok(()) => {
+                database::set_status(&db, &account, timestamp, MessageStatus::Sent);
+                if !missed {
+                    database::remove_outgoing(&db, &account, timestamp);
+                }
+            },
+            // Still sending as far as we know, it goes out on reconnect.
+            // signald may have sent it before timing out, so don't offer a
+            // retry that could send it twice.
+            Err(Error::Socket(_)) | Err(Error::Timeout(_)) => {},
+            Err(_) => database::set_status(&db, &account, timestamp, MessageStatus::Failed)
+        }
+
+        result
+    }
+
+    /// Whether `msg` didn't reach some or all of its recipients and can be
+    /// sent again
+    pub fn can_retry(&self, msg: &Message) -> bool {
+        msg.from_me && msg.status() != MessageStatus::Sending
+            && database::get_outgoing(&self.db(), &msg.account, msg.timestamp).is_some()
+    }
+
+    /// Send one of our messages again after it failed to reach someone.
+    /// It goes to all its recipients, Signal drops the duplicates.
+    pub async fn retry(&self, msg: &Message) -> Result<()> {
+        let request = match database::get_outgoing(&self.db(), &msg.account, msg.timestamp) {
+            Some(request) => request,
+            None => return Ok(())
+        };
+
+        let request = match self.parse_queued(&msg.account, msg.timestamp, &request) {
+            Some(request) => request,
+            None => return Ok(())
+        };
+
+        database::requeue(&self.db(), &msg.account, msg.timestamp);
+        self.send(request).await
+    }
+
+    /// Send the messages every account queued while signald was
+    /// unreachable, oldest first. Returns the ones that went out or failed.
+    pub async fn flush_outbox(&self) -> Vec<Message> {
+        let mut flushed = Vec::new();
+
+        for account in self.accounts() {
+            let queued = database::get_outbox(&self.db(), &account);
+
+            for (timestamp, request) in queued {
+                let request = match self.parse_queued(&account, timestamp, &request) {
+                    Some(request) => request,
+                    None => continue
+                };
+
+                // Offline again, keep the rest for the next reconnect
+                if let Err(Error::Socket(_)) = self.send(request).await {
+                    return flushed;
+                }
+
+                if let Some(msg) = database::get_sent_message(&self.db(), &account, timestamp) {
+                    flushed.push(msg);
+                }
+            }
+        }
+
+        flushed
+    }
+
+    // A corrupt request would fail every flush and retry, so give up on its
+    // message instead
+    fn parse_queued(&self, account: &str, timestamp: i64, request: &str) -> Option<SendRequestV1> {
+        match serde_json::from_str(request) {
+            Ok(request) => Some(request),
+            Err(e) => {
+                log::warn!("Dropping corrupt send request {} from the outbox: {}", timestamp, e);
+
+                let db = self.db();
+                database::remove_outgoing(&db, account, timestamp);
+                database::set_status(&db, account, timestamp, MessageStatus::Failed);
+                None
+            }
+        }
+    }
+
+    /// React to `msg` with `emoji`, or take that reaction back if `remove`
+    /// is set. It's only stored once signald has sent it.
+    pub async fn react(&self, msg: &Message, emoji: &str, remove: bool) -> Result<()> {
+        let account = self.account();
+        let reaction_request = create_react_request(msg, &account, emoji, remove);
+        let timestamp = reaction_request.timestamp.unwrap();
+
+        let response = self.dispatch(
+            "react",
+            SignaldTypes::ReactRequestV1(
+                reaction_request
+            )
+        ).await?;
+
+        match response {
+            SignaldTypes::SendResponse(response) => check_sent(&response)?,
+            _ => return Err(Error::unexpected("react"))
+        }
+
+        database::store_reaction(&self.db(), msg, &account, emoji, timestamp, remove);
+
+        Ok(())
+    }
+
+    async fn get_conversations(&self, account: &String) -> Result<Vec<Rc<Conversation>>> {
+        let contacts = self.dispatch(
+            "list_contacts",
+            SignaldTypes::ListContactsRequestV1(
+                ListContactsRequestV1 {
+                    account: Some(account.clone()),
+                    async_: Some(true)
+                }
+            )
+        ).await?;
+
+        // Contacts are per account too
+        let mut profiles = HashMap::new();
+        let mut conversations = self.get_profiles(account, contacts, &mut profiles)?;
+        *self.contacts.borrow_mut() = profiles;
+
+        let groups = self.dispatch(
+            "list_groups",
+            SignaldTypes::ListGroupsRequestV1(
+                ListGroupsRequestV1 {
+                    account: Some(account.clone()),
+                }
+            )
+        ).await?;
+
+        conversations.append(&mut self.get_groups(account, groups)?);
+
+        Ok(conversations)
+    }
+
+    fn get_profiles(&self, account: &str, contacts: SignaldTypes, profiles: &mut ContactMap) -> Result<Vec<Rc<Conversation>>> {
+        if let SignaldTypes::ProfileListV1(profile_list) = contacts {
+            Ok(profile_list.profiles.unwrap().drain(..).filter_map(|profile| {
+                let number = profile.address.get_number();
+                profiles.insert(number, profile.clone());
+
+                Conversation::new_individual(account, profile, &self.db()).map(|conv| {
+                    Rc::new(conv)
+                })
+            }).collect())
+        } else {
+            Err(Error::unexpected("list_contacts"))
+        }
+    }
+
+    fn get_groups(&self, account: &str, groups: SignaldTypes) -> Result<Vec<Rc<Conversation>>> {
+        if let SignaldTypes::GroupListV1(groups) = groups {
+            Ok(groups.groups.unwrap().drain(..).filter_map(|group| {
+                Conversation::new_group(account, group, &self.db()).map(|conv| {
+                    Rc::new(conv)
+                })
+            }).collect())
+        } else {
+            Err(Error::unexpected("list_groups"))
+        }
+    }
+}
+
+fn construct_typing(username: &String, conversation: &Conversation, typing: bool) -> TypingRequestV1 {
+    TypingRequestV1 {
+        account: Some(username.clone()),
+        address: match &conversation.conversation_type {
+            ConversationType::Individual(conv) => conv.address.clone(),
+            ConversationType::Group(_) => None
+        },
+        group: match &conversation.conversation_type {
+            ConversationType::Group(group) => group.id.clone(),
+            ConversationType::Individual(_) => None
+        },
+        typing: Some(typing),
+        when: Some(chrono::offset::Local::now().timestamp_millis())
+    }
+}
+
+fn construct_message(username: &String, conversation: &Conversation, body: String, quote: Option<JsonQuoteV1>, attachments: Option<Vec<JsonAttachmentV0>>, timestamp: i64) -> SendRequestV1 {
+    SendRequestV1 {
+        username: Some(username.clone()),
+        recipient_address: match &conversation.conversation_type {
+            ConversationType::Individual(conv) => conv.address.clone(),
+            ConversationType::Group(_) => None
+        },
+        recipient_group_id: match &conversation.conversation_type {
+            ConversationType::Group(group) => group.id.clone(),
+            ConversationType::Individual(_) => None
+        },
+        message_body: Some(body),
+        attachments,
+        quote,
+        timestamp: Some(timestamp),
+        mentions: None
+    }
+}
+
+// signald reads the file from `filename`. It's also stored as where to find
+// the attachment so our own bubbles can show it.
+fn construct_attachment(attachment: &OutgoingAttachment) -> Result<JsonAttachmentV0> {
+    // signald only takes sizes that fit an i32
+    let size = match std::fs::metadata(&attachment.path) {
+        Ok(metadata) => Some(i32::try_from(metadata.len()).map_err(|_| {
+            Error::AttachmentTooLarge(attachment.path.clone())
+        })?),
+        Err(_) => None
+    };
+
+    Ok(JsonAttachmentV0 {
+        content_type: Some(attachment.content_type.clone()),
+        id: Some(Uuid::new_v4().to_string()),
+        size,
+        stored_filename: Some(attachment.path.clone()),
+        filename: Some(attachment.path.clone()),
+        custom_filename: None,
+        caption: None,
+        width: None,
+        height: None,
+        voice_note: None,
+        key: None,
+        digest: None,
+        blurhash: None
+    })
+}
+
+fn construct_quote(account: &str, msg: &Message) -> JsonQuoteV1 {
+    JsonQuoteV1 {
+        id: Some(msg.timestamp),
+        author: Some(JsonAddressV1 {
+            // Our own messages are stored with the recipient as their number
+            number: if msg.from_me {
+                Some(account.to_owned())
+            } else {
+                msg.number.clone()
+            },
+            uuid: None,
+            relay: None
+        }),
+        text: Some(msg.body.clone()),
+        attachments: None,
+        mentions: None
+    }
+}
+
+// Older clients show tsdevice: URIs, newer ones sgnl://linkdevice
+fn is_linking_uri(uri: &str) -> bool {
+    (uri.starts_with("sgnl://linkdevice?") || uri.starts_with("tsdevice:/?"))
+        && uri.contains("uuid=")
+        && uri.contains("pub_key=")
+}
+
+// Who signald could and couldn't send to
+fn send_results(response: &SendResponse) -> Vec<(String, bool)> {
+    response.results.iter().flatten().filter_map(|result| {
+        let number = result.address.as_ref()?.number.clone()?;
+        Some((number, result.success.is_some()))
+    }).collect()
+}
+
+// signald answers a send even if it reached nobody, so check the
+// per-recipient results
+fn check_sent(response: &SendResponse) -> Result<()> {
+    let results = match &response.results {
+        Some(results) if !results.is_empty() => results,
+        _ => return Ok(())
+    };
+
+    if results.iter().any(|result| result.success.is_some()) {
+        Ok(())
+    } else {
+        Err(Error::Signald("couldn't be delivered to anyone".to_owned()))
+    }
+}
+
+fn create_react_request(msg: &Message, account: &String, emoji: &str, remove: bool) -> ReactRequestV1 {
+    // Our own messages are stored with the recipient as their number
+    let target_author = if msg.from_me {
+        Some(account.clone())
+    } else {
+        msg.number.clone()
+    };
+
+    ReactRequestV1 {
+        reaction: Some(JsonReactionV1 {
+            emoji: Some(emoji.to_owned()),
+            remove: Some(remove),
+            target_author: Some(JsonAddressV1 {
+                number: target_author,
+                uuid: None,
+                relay: None
+            }),
+            target_sent_timestamp: Some(msg.timestamp)
+        }),
+        recipient_address: msg.number.as_ref().map(|number| {
+            JsonAddressV1 {
+                number: Some(number.clone()),
+                uuid: None,
+                relay: None
+            }
+        }),
+        recipient_group_id: msg.groupid.clone(),
+        timestamp: Some(chrono::offset::Local::now().timestamp_millis()),
+        username: Some(account.clone())
+
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_db;
+    use crate::test_support::mock_signald::{self, MockSignald};
+    use serde_json::Value;
+
+    const ACCOUNT: &str = "+15550000000";
+    const CONTACT: &str = "+15551111111";
+    const OTHER: &str = "+15552222222";
+    const OTHER_ACCOUNT: &str = "+15553333333";
+
+    async fn start_client(mock: &MockSignald) -> Rc<Client> {
+        mock.respond("list_contacts", mock_signald::profile_list(&[(CONTACT, "Alice")]));
+        mock.respond("list_groups", mock_signald::group_list(&[("group-id", "Friends")]));
+
+        let config = BridgeConfig {
+            socket_paths: vec![mock.socket_path()],
+            keepalive: Duration::from_secs(30)
+        };
+        let client = Client::new(config, test_db());
+        client.load_account(ACCOUNT.to_owned()).await.expect("Failed to load account");
+
+        client
+    }
+
+    // A message we sent to the first conversation, as read back from the database
+    fn sent_message(client: &Client) -> Message {
+        let conversation = client.conversations()[0].clone();
+        client.store_outgoing(&client.compose(&conversation, "hi".to_owned(), None, &[]).unwrap());
+
+        database::query_conversation(&client.db(), ACCOUNT, &conversation.conversation_type).pop().unwrap()
+    }
+
+    // The most recent message in the conversation with `CONTACT`
+    fn last_sent(client: &Client) -> Message {
+        let conversation = client.conversation(CONTACT).unwrap();
+        database::query_conversation(&client.db(), ACCOUNT, &conversation.conversation_type).pop().unwrap()
+    }
+
+    #[async_std::test]
+    async fn loads_conversations() {
+        let mock = MockSignald::start().await;
+        let client = start_client(&mock).await;
+
+        let names: Vec<String> = client.conversations().iter().map(|conversation| {
+            conversation.name.clone()
+        }).collect();
+        assert_eq!(names, vec!["Alice", "Friends"]);
+        assert_eq!(client.get_name(CONTACT).as_deref(), Some("Alice"));
+    }
+
+    #[async_std::test]
+    async fn routes_incoming_messages_to_conversations() {
+        let mock = MockSignald::start().await;
+        let client = start_client(&mock).await;
+
+        mock.wait_for_client().await;
+        mock.push_incoming(mock_signald::text_message(ACCOUNT, CONTACT, 1000, "hello")).await;
+
+        let conversation = loop {
+            if let Event::NewMessage { conversation, message } = client.next_event().await {
+                assert_eq!(message.body, "hello");
+                break conversation.expect("Message should belong to Alice");
+            }
+        };
+
+        assert_eq!(conversation.name, "Alice");
+        assert_eq!(*conversation.new_msgs.borrow(), 1);
+        // Most recent conversation first
+        assert_eq!(client.conversations()[0].name, "Alice");
+    }
+
+    #[async_std::test]
+    async fn receives_messages_for_every_account() {
+        let mock = MockSignald::start().await;
+        let client = start_client(&mock).await;
+        mock.respond("list_accounts", mock_signald::account_list(&[OTHER_ACCOUNT, ACCOUNT]));
+
+        assert_eq!(client.load_accounts().await.expect("Failed to list accounts"), vec![OTHER_ACCOUNT, ACCOUNT]);
+        client.subscribe().await.expect("Failed to subscribe");
+        let subscribed: Vec<Value> = mock.requests("subscribe").drain(..).map(|request| {
+            request["account"].clone()
+        }).collect();
+        assert_eq!(subscribed, vec![OTHER_ACCOUNT, ACCOUNT]);
+
+        mock.push_incoming(mock_signald::typing_message(OTHER_ACCOUNT, CONTACT, None, true)).await;
+        mock.push_incoming(mock_signald::text_message(OTHER_ACCOUNT, CONTACT, 1000, "elsewhere")).await;
+
+        // Typing to another account isn't shown
+        let (conversation, message) = loop {
+            match client.next_event().await {
+                Event::NewMessage { conversation, message } => break (conversation, message),
+                Event::Typing { .. } => panic!("Typing to another account was passed on"),
+                _ => {}
+            }
+        };
+        assert!(conversation.is_none());
+        assert_eq!(message.account, OTHER_ACCOUNT);
+        assert!(client.conversation(CONTACT).unwrap().typists().is_empty());
+        assert_eq!(client.unread_count(ACCOUNT), 0);
+        assert_eq!(client.unread_count(OTHER_ACCOUNT), 1);
+
+        client.load_account(OTHER_ACCOUNT.to_owned()).await.expect("Failed to switch account");
+        assert_eq!(client.account(), OTHER_ACCOUNT);
+        assert_eq!(client.last_account().as_deref(), Some(OTHER_ACCOUNT));
+        assert_eq!(*client.conversation(CONTACT).unwrap().new_msgs.borrow(), 1);
+        assert_eq!(client.unread_count(OTHER_ACCOUNT), 1);
+    }
+
+    #[async_std::test]
+    async fn syncs_messages_read_elsewhere() {
+        let mock = MockSignald::start().await;
+        let client = start_client(&mock).await;
+
+        mock.wait_for_client().await;
+        mock.push_incoming(mock_signald::text_message(ACCOUNT, CONTACT, 1000, "hello")).await;
+        mock.push_incoming(mock_signald::text_message(ACCOUNT, CONTACT, 2000, "again")).await;
+        mock.push_incoming(mock_signald::read_sync_message(ACCOUNT, &[(CONTACT, 1000), (CONTACT, 2000)])).await;
+
+        let conversations = loop {
+            if let Event::Read { conversations } = client.next_event().await {
+                break conversations;
+            }
+        };
+
+        assert_eq!(conversations.len(), 1);
+        assert_eq!(conversations[0].name, "Alice");
+        assert_eq!(*conversations[0].new_msgs.borrow(), 0);
+        assert!(conversations[0].unread.borrow().is_empty());
+
+        let (count, _) = database::get_unread(&client.db(), ACCOUNT, Some(&CONTACT.to_owned()), None);
+        assert_eq!(count, 0);
+    }
+
+    #[async_std::test]
+    async fn marks_conversations_read() {
+        let mock = MockSignald::start().await;
+        let client = start_client(&mock).await;
+
+        mock.wait_for_client().await;
+        mock.push_incoming(mock_signald::text_message(ACCOUNT, CONTACT, 1000, "hello")).await;
+
+        let conversation = loop {
+            if let Event::NewMessage { conversation: Some(conversation), .. } = client.next_event().await {
+                break conversation;
+            }
+        };
+
+        client.mark_read(&conversation).await.expect("Failed to mark read");
+
+        assert_eq!(*conversation.new_msgs.borrow(), 0);
+        let request = mock.wait_for_request("mark_read").await;
+        assert_eq!(request["timestamps"][0], 1000);
+        let (unread, _) = database::get_unread(&client.db(), ACCOUNT, Some(&CONTACT.to_owned()), None);
+        assert_eq!(unread, 0);
+    }
+
+    #[async_std::test]
+    async fn quotes_replied_messages() {
+        let mock = MockSignald::start().await;
+        let client = start_client(&mock).await;
+
+        let original = sent_message(&client);
+        let conversation = client.conversations()[0].clone();
+        let reply = client.compose(&conversation, "reply".to_owned(), Some(&original), &[]).unwrap();
+
+        let quote = reply.quote.as_ref().expect("Reply should quote the original");
+        assert_eq!(quote.id, Some(original.timestamp));
+        assert_eq!(quote.author.as_ref().unwrap().number.as_deref(), Some(ACCOUNT));
+        assert_eq!(quote.text.as_deref(), Some("hi"));
+
+        let stored = client.store_outgoing(&reply);
+        assert_eq!(stored.quote_timestamp, Some(original.timestamp));
+        assert_eq!(stored.quote_author.as_deref(), Some(ACCOUNT));
+
+        let quoted = database::find_authored_message(&client.db(), original.timestamp, ACCOUNT, ACCOUNT, &None);
+        assert_eq!(quoted.unwrap().body, "hi");
+    }
+
+    #[async_std::test]
+    async fn stores_outgoing_attachments() {
+        let mock = MockSignald::start().await;
+        let client = start_client(&mock).await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cat.png").to_str().unwrap().to_owned();
+        std::fs::write(&path, b"not really a png").unwrap();
+
+        let conversation = client.conversations()[0].clone();
+        let msg = client.compose(&conversation, String::new(), None, &[OutgoingAttachment {
+            path: path.clone(),
+            content_type: "image/png".to_owned()
+        }]).unwrap();
+
+        let attachment = &msg.attachments.as_ref().unwrap()[0];
+        assert_eq!(attachment.filename.as_deref(), Some(path.as_str()));
+        assert_eq!(attachment.size, Some(16));
+
+        let stored = client.store_outgoing(&msg);
+        let id = stored.attachments.as_ref().unwrap().lines().next().unwrap();
+        let attachment = database::get_attachment(&client.db(), id).unwrap();
+        assert_eq!(attachment.content_type, "image/png");
+        assert_eq!(attachment.filename.as_deref(), Some(path.as_str()));
+    }
+
+    #[async_std::test]
+    async fn rejects_oversized_attachments() {
+        let mock = MockSignald::start().await;
+        let client = start_client(&mock).await;
+
+        // Sparse, so it doesn't really take up the space
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("huge.mp4").to_str().unwrap().to_owned();
+        std::fs::File::create(&path).unwrap().set_len(i32::MAX as u64 + 1).unwrap();
+
+        let conversation = client.conversations()[0].clone();
+        let msg = client.compose(&conversation, String::new(), None, &[OutgoingAttachment {
+            path,
+            content_type: "video/mp4".to_owned()
+        }]);
+
+        assert!(matches!(msg, Err(Error::AttachmentTooLarge(_))));
+    }
+
+    #[async_std::test]
+    async fn lists_conversation_media() {
+        let mock = MockSignald::start().await;
+        let client = start_client(&mock).await;
+        let conversation = client.conversations()[0].clone();
+
+        for (name, content_type) in [("a.png", "image/png"), ("b.pdf", "application/pdf"), ("c.mp4", "video/mp4")].iter() {
+            let attachment = OutgoingAttachment {
+                path: format!("/tmp/{}", name),
+                content_type: content_type.to_string()
+            };
+            client.store_outgoing(&client.compose(&conversation, String::new(), None, &[attachment]).unwrap());
+        }
+
+        let media: Vec<String> = database::get_conversation_media(&client.db(), ACCOUNT, &conversation.conversation_type)
+            .drain(..)
+            .map(|attachment| attachment.content_type)
+            .collect();
+        assert_eq!(media, vec!["image/png", "video/mp4"]);
+    }
+
+    #[async_std::test]
+    async fn tracks_who_is_typing() {
+        let mock = MockSignald::start().await;
+        let client = start_client(&mock).await;
+
+        mock.wait_for_client().await;
+        mock.push_incoming(mock_signald::typing_message(ACCOUNT, CONTACT, Some("group-id"), true)).await;
+
+        let conversation = loop {
+            if let Event::Typing { conversation } = client.next_event().await {
+                break conversation.expect("Typing should be in the group");
+            }
+        };
+        assert_eq!(conversation.name, "Friends");
+        assert_eq!(conversation.typists(), vec![CONTACT]);
+
+        // Typing in a group doesn't count for the 1:1 conversation
+        assert!(client.conversation(CONTACT).unwrap().typists().is_empty());
+
+        mock.push_incoming(mock_signald::typing_message(ACCOUNT, CONTACT, Some("group-id"), false)).await;
+        loop {
+            if let Event::Typing { .. } = client.next_event().await {
+                break;
+            }
+        }
+        assert!(conversation.typists().is_empty());
+    }
+
+    #[async_std::test]
+    async fn only_sends_typing_when_enabled() {
+        let mock = MockSignald::start().await;
+        let client = start_client(&mock).await;
+        let conversation = client.conversation(CONTACT).unwrap();
+
+        client.set_flag(SEND_TYPING, false);
+        client.typing(&conversation, true).await.expect("Failed to skip typing");
+        assert!(mock.requests("typing").is_empty());
+
+        client.set_flag(SEND_TYPING, true);
+        client.typing(&conversation, true).await.expect("Failed to send typing");
+        assert_eq!(mock.requests("typing").len(), 1);
+    }
+
+    #[async_std::test]
+    async fn remembers_muted_conversations() {
+        let mock = MockSignald::start().await;
+        let client = start_client(&mock).await;
+
+        let conversation = client.conversation("group-id").expect("Group should be loaded");
+        assert!(!*conversation.muted.borrow());
+        client.set_muted(&conversation, true);
+
+        client.load_account(ACCOUNT.to_owned()).await.expect("Failed to reload account");
+        assert!(*client.conversation("group-id").unwrap().muted.borrow());
+        assert!(!*client.conversation(CONTACT).unwrap().muted.borrow());
+    }
+
+    #[async_std::test]
+    async fn replaces_and_removes_own_reactions() {
+        let mock = MockSignald::start().await;
+        let client = start_client(&mock).await;
+        mock.respond("react", mock_signald::send_results(&[(CONTACT, true)]));
+
+        let msg = sent_message(&client);
+
+        client.react(&msg, "👍", false).await.expect("Failed to react");
+        client.react(&msg, "❤️", false).await.expect("Failed to react");
+        let reactions = database::get_reactions(&client.db(), &msg);
+        assert_eq!(reactions.len(), 1);
+        assert_eq!(reactions[0].emoji, "❤️");
+        assert_eq!(reactions[0].author, ACCOUNT);
+
+        let request = mock.requests("react").pop().unwrap();
+        assert_eq!(request["reaction"]["target_author"]["number"], ACCOUNT);
+
+        client.react(&msg, "❤️", true).await.expect("Failed to remove reaction");
+        assert!(database::get_reactions(&client.db(), &msg).is_empty());
+        assert_eq!(mock.requests("react").pop().unwrap()["reaction"]["remove"], true);
+    }
+
+    #[async_std::test]
+    async fn gives_quick_messages_their_own_timestamps() {
+        let mock = MockSignald::start().await;
+        let client = start_client(&mock).await;
+        let conversation = client.conversation(CONTACT).unwrap();
+
+        // Well within a millisecond of each other
+        let first = client.compose(&conversation, "one".to_owned(), None, &[]).unwrap();
+        let second = client.compose(&conversation, "two".to_owned(), None, &[]).unwrap();
+        client.store_outgoing(&first);
+        client.store_outgoing(&second);
+
+        assert!(second.timestamp > first.timestamp);
+        assert_eq!(database::query_conversation(&client.db(), ACCOUNT, &conversation.conversation_type).len(), 2);
+    }
+
+    #[async_std::test]
+    async fn tracks_send_status() {
+        let mock = MockSignald::start().await;
+        let client = start_client(&mock).await;
+        let conversation = client.conversation(CONTACT).unwrap();
+
+        let msg = client.compose(&conversation, "hi".to_owned(), None, &[]).unwrap();
+        client.store_outgoing(&msg);
+        assert_eq!(last_sent(&client).status(), MessageStatus::Sending);
+
+        mock.respond("send", mock_signald::send_results(&[(CONTACT, true)]));
+        client.send(msg).await.expect("Failed to send");
+        let sent = database::query_conversation(&client.db(), ACCOUNT, &conversation.conversation_type)[0].clone();
+        assert_eq!(sent.status(), MessageStatus::Sent);
+
+        let msg = client.compose(&conversation, "again".to_owned(), None, &[]).unwrap();
+        client.store_outgoing(&msg);
+        mock.respond("send", mock_signald::send_results(&[(CONTACT, false)]));
+        assert!(client.send(msg).await.is_err());
+        let failed = database::query_conversation(&client.db(), ACCOUNT, &conversation.conversation_type).pop().unwrap();
+        assert_eq!(failed.status(), MessageStatus::Failed);
+    }
+
+    #[async_std::test]
+    async fn records_results_per_recipient() {
+        let mock = MockSignald::start().await;
+        let client = start_client(&mock).await;
+        mock.respond("send", mock_signald::send_results(&[(CONTACT, true), (OTHER, false)]));
+
+        let conversation = client.conversation(CONTACT).unwrap();
+        let msg = client.compose(&conversation, "hi".to_owned(), None, &[]).unwrap();
+        client.store_outgoing(&msg);
+        client.send(msg).await.expect("Sent to someone");
+
+        let sent = last_sent(&client);
+        assert_eq!(sent.status(), MessageStatus::Sent);
+        let results: Vec<(String, MessageStatus)> = database::get_receipts(&client.db(), &sent).iter().map(|receipt| {
+            (receipt.member.clone(), receipt.status())
+        }).collect();
+        assert_eq!(results, vec![
+            (CONTACT.to_owned(), MessageStatus::Sent),
+            (OTHER.to_owned(), MessageStatus::Failed)
+        ]);
+
+        // Flushing only picks up messages still sending, this one waits
+        // to be retried by hand
+        assert!(database::get_outbox(&client.db(), ACCOUNT).is_empty());
+        assert!(client.can_retry(&sent));
+    }
+
+    #[async_std::test]
+    async fn flushes_queued_messages() {
+        let mock = MockSignald::start().await;
+        let client = start_client(&mock).await;
+
+        // Stored but never sent, e.g. the app quit while offline
+        let msg = sent_message(&client);
+        assert_eq!(database::get_outbox(&client.db(), ACCOUNT).len(), 1);
+
+        mock.respond("send", mock_signald::send_results(&[(CONTACT, true)]));
+        let flushed = client.flush_outbox().await;
+
+        assert_eq!(flushed.len(), 1);
+        assert_eq!(flushed[0].timestamp, msg.timestamp);
+        assert_eq!(flushed[0].status(), MessageStatus::Sent);
+        assert!(database::get_outbox(&client.db(), ACCOUNT).is_empty());
+        assert_eq!(mock.requests("send").len(), 1);
+    }
+
+    #[async_std::test]
+    async fn drops_corrupt_queued_messages() {
+        let mock = MockSignald::start().await;
+        let client = start_client(&mock).await;
+
+        let msg = sent_message(&client);
+        database::queue_outgoing(&client.db(), ACCOUNT, msg.timestamp, "not a send request");
+
+        assert!(client.flush_outbox().await.is_empty());
+        assert!(database::get_outbox(&client.db(), ACCOUNT).is_empty());
+        assert_eq!(last_sent(&client).status(), MessageStatus::Failed);
+        assert!(mock.requests("send").is_empty());
+    }
+
+    #[async_std::test]
+    async fn keeps_timed_out_messages_sending() {
+        let mock = MockSignald::start().await;
+        let client = start_client(&mock).await;
+        mock.never_respond("send");
+        client.set_timeout("send", Duration::from_millis(300));
+
+        let conversation = client.conversation(CONTACT).unwrap();
+        let msg = client.compose(&conversation, "hi".to_owned(), None, &[]).unwrap();
+        client.store_outgoing(&msg);
+        assert!(matches!(client.send(msg).await, Err(Error::Timeout("send"))));
+
+        let sent = last_sent(&client);
+        assert_eq!(sent.status(), MessageStatus::Sending);
+        assert!(!client.can_retry(&sent));
+        assert_eq!(database::get_outbox(&client.db(), ACCOUNT).len(), 1);
+    }
+
+    #[async_std::test]
+    async fn retries_failed_messages() {
+        let mock = MockSignald::start().await;
+        let client = start_client(&mock).await;
+        mock.respond("send", mock_signald::send_results(&[(CONTACT, false)]));
+
+        let conversation = client.conversation(CONTACT).unwrap();
+        let msg = client.compose(&conversation, "hi".to_owned(), None, &[]).unwrap();
+        client.store_outgoing(&msg);
+        assert!(client.send(msg).await.is_err());
+
+        // Failed messages wait for the user rather than the next flush
+        let failed = last_sent(&client);
+        assert!(client.can_retry(&failed));
+        assert!(client.flush_outbox().await.is_empty());
+
+        mock.respond("send", mock_signald::send_results(&[(CONTACT, true)]));
+        client.retry(&failed).await.expect("Failed to retry");
+
+        let sent = last_sent(&client);
+        assert_eq!(sent.status(), MessageStatus::Sent);
+        assert!(!client.can_retry(&sent));
+        assert_eq!(mock.requests("send").len(), 2);
+    }
+
+    #[async_std::test]
+    async fn registers_new_numbers() {
+        let mock = MockSignald::start().await;
+        let client = start_client(&mock).await;
+        mock.respond_error("register", "CaptchaRequiredError", "captcha required");
+
+        let result = client.register(OTHER, true, None).await;
+        assert!(matches!(result, Err(Error::CaptchaRequired(_))));
+
+        mock.respond("register", serde_json::json!({}));
+        client.register(OTHER, true, Some("token".to_owned())).await.expect("Failed to register");
+        let request = mock.requests("register").pop().unwrap();
+        assert_eq!(request["account"], OTHER);
+        assert_eq!(request["voice"], true);
+        assert_eq!(request["captcha"], "token");
+
+        mock.respond_error("verify", "AccountLockedError", "account locked");
+        let result = client.verify(OTHER, "123-456", None).await;
+        assert!(matches!(result, Err(Error::RegistrationLocked(_))));
+
+        mock.respond("verify", serde_json::json!({ "account_id": OTHER, "device_id": 1 }));
+        let account = client.verify(OTHER, "123-456", Some("1234".to_owned())).await.expect("Failed to verify");
+        assert_eq!(account, OTHER);
+        let request = mock.requests("verify").pop().unwrap();
+        assert_eq!(request["code"], "123456");
+        assert_eq!(request["pin"], "1234");
+    }
+
+    #[async_std::test]
+    async fn manages_linked_devices() {
+        let mock = MockSignald::start().await;
+        let client = start_client(&mock).await;
+        mock.respond("list_accounts", mock_signald::account_list(&[ACCOUNT]));
+        mock.respond("get_linked_devices", mock_signald::linked_devices(&[(1, "Phone"), (2, "Laptop")]));
+
+        assert_eq!(client.device_id().await.expect("Failed to list accounts"), Some(PRIMARY_DEVICE_ID));
+        let names: Vec<String> = client.linked_devices().await.expect("Failed to list devices").drain(..).map(|device| {
+            device.name.unwrap_or_default()
+        }).collect();
+        assert_eq!(names, vec!["Phone", "Laptop"]);
+
+        client.unlink_device(2).await.expect("Failed to unlink");
+        let request = mock.requests("remove_linked_device").pop().unwrap();
+        assert_eq!(request["account"], ACCOUNT);
+        assert_eq!(request["device_id"], 2);
+
+        let result = client.add_device("https://signal.org").await;
+        assert!(matches!(result, Err(Error::InvalidLinkingUri(_))));
+        assert!(mock.requests("add_device").is_empty());
+
+        let uri = "sgnl://linkdevice?uuid=abc&pub_key=def";
+        client.add_device(&format!(" {}\n", uri)).await.expect("Failed to add device");
+        assert_eq!(mock.requests("add_device").pop().unwrap()["uri"], uri);
+    }
+
+    #[async_std::test]
+    async fn keeps_reactions_signald_failed_to_send() {
+        let mock = MockSignald::start().await;
+        let client = start_client(&mock).await;
+        mock.respond("react", mock_signald::send_results(&[(CONTACT, false)]));
+
+        let msg = sent_message(&client);
+
+        assert!(client.react(&msg, "👍", false).await.is_err());
+        assert!(database::get_reactions(&client.db(), &msg).is_empty());
+    }
+}
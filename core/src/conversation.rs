@@ -0,0 +1,192 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use diesel::sqlite::SqliteConnection;
+
+use signald::types::{ProfileV1, JsonGroupV2InfoV1};
+
+use crate::database;
+use crate::signal_type_utils::*;
+use crate::models::NewMessage;
+
+/// How long someone counts as typing without hearing from them again.
+/// Signal clients repeat "started typing" more often than this.
+pub const TYPING_TIMEOUT: Duration = Duration::from_secs(15);
+
+pub enum ConversationType {
+    Individual(ProfileV1),
+    Group(JsonGroupV2InfoV1)
+}
+
+pub struct Conversation {
+    /// The account this conversation belongs to
+    pub account: String,
+    pub conversation_type: ConversationType,
+    pub name: String,
+    pub number: Option<String>,
+    pub groupid: Option<String>,
+    /// Who's typing, and when that expires
+    pub typing: RefCell<HashMap<String, Instant>>,
+    pub last_message_time: RefCell<i64>,
+    pub is_active: RefCell<bool>,
+    pub new_msgs: RefCell<usize>,
+    pub unread: RefCell<HashMap<String, Vec<i64>>>,
+    pub muted: RefCell<bool>
+}
+
+impl Conversation {
+    pub fn new_individual(account: &str, profile: ProfileV1, db: &SqliteConnection) -> Option<Self> {
+        // Ask for profile from signal if known profile is incomplete
+        let name = profile.get_name();
+        let number = profile.address.get_number();
+        let (new_msgs, unread) = database::get_unread(db, account, Some(&number), None);
+        let muted = database::is_muted(db, account, &number);
+
+        if name.is_empty() {
+            None
+        } else {
+            Some(Conversation {
+                account: account.to_owned(),
+                conversation_type: ConversationType::Individual(profile),
+                name,
+                number: Some(number),
+                groupid: None,
+                typing: RefCell::new(HashMap::new()),
+                last_message_time: RefCell::new(i64::MIN),
+                is_active: RefCell::new(false),
+                new_msgs: RefCell::new(new_msgs),
+                unread: RefCell::new(unread),
+                muted: RefCell::new(muted)
+            })
+        }
+    }
+
+    pub fn new_group(account: &str, group: JsonGroupV2InfoV1, db: &SqliteConnection) -> Option<Self> {
+        let name = group.title.unwrap_clone();
+        let groupid = group.id.unwrap_clone();
+        let (new_msgs, unread) = database::get_unread(db, account, None, Some(&groupid));
+        let muted = database::is_muted(db, account, &groupid);
+
+        if name.is_empty() {
+            None
+        } else {
+            Some(Conversation {
+                account: account.to_owned(),
+                conversation_type: ConversationType::Group(group),
+                name,
+                number: None,
+                groupid: Some(groupid),
+                typing: RefCell::new(HashMap::new()),
+                last_message_time: RefCell::new(i64::MIN),
+                is_active: RefCell::new(false),
+                new_msgs: RefCell::new(new_msgs),
+                unread: RefCell::new(unread),
+                muted: RefCell::new(muted)
+            })
+        }
+    }
+
+    pub fn set_last_message(&self, db: &SqliteConnection) {
+        let msg = database::get_most_recent_message(
+            db,
+            &self.account,
+            &self.number,
+            &self.groupid
+        );
+        if let Some(msg) = msg {
+            self.last_message_time.replace(msg.timestamp);
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        match &self.conversation_type {
+            ConversationType::Individual(profile) => {
+                profile.name.as_ref().unwrap().as_str()
+            },
+            ConversationType::Group(group) => {
+                group.title.as_ref().unwrap().as_str()
+            }
+        }
+    }
+
+    pub fn set_typing(&self, number: &str, started: bool) {
+        let mut typing = self.typing.borrow_mut();
+        if started {
+            typing.insert(number.to_owned(), Instant::now() + TYPING_TIMEOUT);
+        } else {
+            typing.remove(number);
+        }
+    }
+
+    /// Numbers of everyone currently typing here
+    pub fn typists(&self) -> Vec<String> {
+        let now = Instant::now();
+        let mut typing = self.typing.borrow_mut();
+        typing.retain(|_, expires| *expires > now);
+
+        let mut typists: Vec<String> = typing.keys().cloned().collect();
+        typists.sort();
+        typists
+    }
+
+    /// Identifies the conversation: the group id for groups, otherwise
+    /// the contact's number
+    pub fn key(&self) -> &str {
+        self.groupid.as_ref()
+            .or_else(|| self.number.as_ref())
+            .map(|key| key.as_str())
+            .unwrap_or_default()
+    }
+
+    /// Whether a message with this number and group belongs here. Group
+    /// messages carry the sender's number, so groups only match on id.
+    pub fn contains(&self, number: &Option<String>, groupid: &Option<String>) -> bool {
+        match groupid {
+            Some(_) => self.groupid == *groupid,
+            None => self.groupid.is_none() && self.number == *number
+        }
+    }
+
+    pub fn notify_msg(&self, msg: &NewMessage) {
+        // Sending a message means they've stopped typing
+        if let Some(number) = &msg.number {
+            self.typing.borrow_mut().remove(number);
+        }
+
+        if !(*self.is_active.borrow()) {
+            self.new_msgs.replace_with(|&mut num_msgs| num_msgs + 1);
+            let unread = &mut *self.unread.borrow_mut();
+            let number = msg.number.unwrap_clone();
+            if let Some(timestamps) = unread.get_mut(&number) {
+                timestamps.push(msg.timestamp);
+            } else {
+                unread.insert(number, vec![msg.timestamp]);
+            }
+        }
+    }
+
+    /// Forget unread messages that were read on another of our devices,
+    /// `read` being timestamps by sender. Returns whether any were here.
+    pub fn read_elsewhere(&self, read: &HashMap<String, Vec<i64>>) -> bool {
+        let unread = &mut *self.unread.borrow_mut();
+        let mut changed = false;
+
+        for (number, timestamps) in read {
+            if let Some(unread_timestamps) = unread.get_mut(number) {
+                let before = unread_timestamps.len();
+                unread_timestamps.retain(|timestamp| !timestamps.contains(timestamp));
+                changed |= unread_timestamps.len() != before;
+
+                if unread_timestamps.is_empty() {
+                    unread.remove(number);
+                }
+            }
+        }
+
+        if changed {
+            self.new_msgs.replace(unread.values().map(Vec::len).sum());
+        }
+
+        changed
+    }
+}
@@ -0,0 +1,804 @@
+use diesel::prelude::*;
+use diesel::sqlite::{Sqlite, SqliteConnection};
+use dotenv::dotenv;
+use std::env;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use signald::types::{JsonAttachmentV0, JsonMentionV1};
+
+use crate::models::{Attachment, MessageStatus, NewAttachment, NewMessage, NewReaction, NewReceipt, Message, Reaction,
+                    Receipt};
+use crate::schema::{attachments, messages};
+use crate::conversation::ConversationType;
+
+pub fn establish_connection() -> SqliteConnection {
+    dotenv().ok();
+
+    let database_url = env::var("DATABASE_URL")
+        .expect("DATABASE_URL must be set");
+    SqliteConnection::establish(&database_url)
+        .expect(&format!("Error connecting to {}", database_url))
+}
+
+pub fn store_message(db: &SqliteConnection, msg: &NewMessage) {
+    diesel::insert_into(messages::table)
+        .values(msg)
+        .execute(db)
+        .expect("Failed to insert message into db");
+}
+
+pub fn store_attachments(db: &SqliteConnection, attachments: Option<&Vec<JsonAttachmentV0>>) -> Option<String> {
+    attachments.map(|attachments| {
+        attachments.iter().fold(String::new(), |mut acc, attachment| {
+            store_single_attachment(db, attachment);
+            acc.push_str(attachment.id.as_ref().unwrap());
+            acc.push('\n');
+            acc
+        })
+    })
+}
+
+fn store_single_attachment(db: &SqliteConnection, attachment: &JsonAttachmentV0) {
+    let id = attachment.id.as_ref().unwrap().as_str();
+    let blurhash = attachment.blurhash.as_ref().map(|blurhash| {
+        blurhash.as_str()
+    });
+    let content_type = attachment.content_type.as_ref().unwrap().as_str();
+    let filename = attachment.stored_filename.as_ref().map(|filename| {
+        filename.as_str()
+    });
+
+    let attachment = NewAttachment {
+        id,
+        blurhash,
+        content_type,
+        filename,
+        width: attachment.width,
+        height: attachment.height
+    };
+
+    diesel::insert_into(attachments::table)
+        .values(&attachment)
+        .execute(db)
+        .expect("Failed to insert attachment into db");
+}
+
+pub fn get_attachment(db: &SqliteConnection, id_q: &str) -> Option<Attachment> {
+    use crate::schema::attachments::dsl::*;
+    attachments.filter(id.eq(id_q.to_owned()))
+        .get_result(db)
+        .ok()
+}
+
+pub fn convert_mentions(mentions: &Option<Vec<JsonMentionV1>>) -> (Option<Vec<u8>>, Option<Vec<u8>>) {
+    let mentions_id = mentions.as_ref().map(|mentions| {
+        mentions.iter().fold(Vec::new(), |mut acc, mention| {
+            acc.extend_from_slice(
+                Uuid::parse_str(mention.uuid.as_ref().unwrap()).unwrap().as_bytes()
+            );
+            acc
+        })
+    });
+
+    let mentions_start = mentions.as_ref().map(|mentions| {
+        mentions.iter().fold(Vec::new(), |mut acc, mention| {
+            acc.extend_from_slice(
+                &mention.start.unwrap().to_le_bytes()
+            );
+            acc
+        })
+    });
+
+    (mentions_id, mentions_start)
+}
+
+/// Messages in `conversation` of `account_q`
+pub fn query_conversation(db: &SqliteConnection, account_q: &str, conversation: &ConversationType) -> Vec<Message> {
+    use crate::schema::messages::dsl::*;
+    let messages_q = messages.filter(account.eq(account_q));
+    match conversation {
+        ConversationType::Individual(profile) => {
+            messages_q.filter(
+                number.eq(
+                    profile.address.as_ref().unwrap().number.as_ref().unwrap().as_str()
+                )
+            )
+                .filter(groupid.is_null())
+                .load(db)
+                .expect("Failed to load messages")
+        },
+        ConversationType::Group(group) => {
+            messages_q.filter(groupid.eq(group.id.as_ref().unwrap().as_str()))
+                .load(db)
+                .expect("Failed to load messages")
+        }
+    }
+}
+
+/// Images and videos shared in a conversation, oldest first
+pub fn get_conversation_media(db: &SqliteConnection, account_q: &str, conversation: &ConversationType) -> Vec<Attachment> {
+    let mut msgs = query_conversation(db, account_q, conversation);
+    msgs.sort_by_key(|msg| msg.timestamp);
+
+    msgs.iter()
+        .filter_map(|msg| msg.attachments.as_ref())
+        .flat_map(|ids| ids.lines())
+        .filter_map(|id| get_attachment(db, id))
+        .filter(|attachment| {
+            attachment.content_type.starts_with("image/") || attachment.content_type.starts_with("video/")
+        })
+        .collect()
+}
+
+pub fn get_message(db: &SqliteConnection, account_q: &str, timestamp_q: i64, number_q: Option<String>, from_me_q: bool, groupid_q: Option<String>) -> Message {
+    let query = construct_message_query(account_q, timestamp_q, number_q, from_me_q, groupid_q);
+
+    query.get_result(db)
+        .expect("Can't find message")
+}
+
+/// Record a delivery or read receipt from `member_q` for our messages sent
+/// from `account_q` at `timestamps`, returning the messages it moved forward
+pub fn store_receipt(db: &SqliteConnection, account_q: &str, timestamps: &[i64], member_q: &str, status_q: MessageStatus, when: i64) -> Vec<Message> {
+    use crate::schema::messages::dsl::*;
+
+    db.transaction::<_, diesel::result::Error, _>(|| {
+        let mut updated = Vec::new();
+
+        for timestamp_q in timestamps {
+            let query = messages.filter(account.eq(account_q))
+                .filter(timestamp.eq(timestamp_q))
+                .filter(from_me.eq(true));
+
+            if query.count().get_result::<i64>(db)? == 0 {
+                continue;
+            }
+
+            store_member_receipt(db, account_q, *timestamp_q, member_q, status_q, when)?;
+
+            // A receipt for a message we thought failed means it got there
+            let changed = diesel::update(query.filter(status.lt(status_q as i32)))
+                .set(status.eq(status_q as i32))
+                .execute(db)?;
+
+            if status_q == MessageStatus::Read {
+                diesel::update(query)
+                    .set(is_read.eq(true))
+                    .execute(db)?;
+            }
+
+            if changed > 0 {
+                updated.append(&mut query.load::<Message>(db)?);
+            }
+        }
+
+        Ok(updated)
+    }).expect("Couldn't store receipt")
+}
+
+// Receipts only move forward, a late delivery receipt mustn't undo a
+// read. No receipt yet compares less than any.
+fn store_member_receipt(db: &SqliteConnection, account_q: &str, timestamp_q: i64, member_q: &str, status_q: MessageStatus, when: i64) -> QueryResult<()> {
+    use crate::schema::receipts::dsl::*;
+
+    let current = receipts.find((account_q, timestamp_q, member_q))
+        .select(status)
+        .first::<i32>(db)
+        .optional()?;
+
+    if current < Some(status_q as i32) {
+        diesel::replace_into(receipts)
+            .values(&NewReceipt {
+                message_timestamp: timestamp_q,
+                member: member_q,
+                status: status_q as i32,
+                timestamp: when,
+                account: account_q
+            })
+            .execute(db)?;
+    }
+
+    Ok(())
+}
+
+/// Record signald's answer to sending one of our messages, whether it
+/// reached each recipient
+pub fn store_send_results(db: &SqliteConnection, account_q: &str, timestamp_q: i64, results: &[(String, bool)], when: i64) {
+    db.transaction::<_, diesel::result::Error, _>(|| {
+        for (member_q, sent) in results {
+            let status_q = if *sent { MessageStatus::Sent } else { MessageStatus::Failed };
+            store_member_receipt(db, account_q, timestamp_q, member_q, status_q, when)?;
+        }
+
+        Ok(())
+    }).expect("Couldn't store send results");
+}
+
+/// Who got or read one of our messages, furthest along first
+pub fn get_receipts(db: &SqliteConnection, msg: &Message) -> Vec<Receipt> {
+    use crate::schema::receipts::dsl::*;
+
+    receipts
+        .filter(account.eq(&msg.account))
+        .filter(message_timestamp.eq(msg.timestamp))
+        .order_by((status.desc(), timestamp.asc()))
+        .load::<Receipt>(db)
+        .expect("Error loading receipts")
+}
+
+/// Move one of our messages on from sending once signald has answered.
+/// Receipts can beat the answer here, so anything further along is kept.
+pub fn set_status(db: &SqliteConnection, account_q: &str, timestamp_q: i64, status_q: MessageStatus) {
+    use crate::schema::messages::dsl::*;
+
+    let query = messages.filter(account.eq(account_q))
+        .filter(timestamp.eq(timestamp_q))
+        .filter(from_me.eq(true))
+        .filter(status.eq(MessageStatus::Sending as i32));
+
+    diesel::update(query)
+        .set(status.eq(status_q as i32))
+        .execute(db)
+        .expect("Couldn't update message status");
+}
+
+pub fn read_msgs(db: &SqliteConnection, account_q: &str, timestamps: &Vec<i64>, number_q: &String) {
+    use crate::schema::messages::dsl::*;
+
+    // Number must be some because this is for received messages
+    for timestamp_q in timestamps {
+        let query = messages.filter(account.eq(account_q))
+            .filter(timestamp.eq(timestamp_q))
+            .filter(number.eq(number_q));
+
+        diesel::update(query)
+            .set(is_read.eq(true))
+            .execute(db)
+            .expect("Couldn't mark message as read");
+    }
+}
+
+/// One of our messages by when we sent it
+pub fn get_sent_message(db: &SqliteConnection, account_q: &str, timestamp_q: i64) -> Option<Message> {
+    use crate::schema::messages::dsl::*;
+
+    messages.filter(account.eq(account_q))
+        .filter(timestamp.eq(timestamp_q))
+        .filter(from_me.eq(true))
+        .first(db)
+        .ok()
+}
+
+/// Find a message by who sent it and when, which is how reactions and
+/// quotes refer to their target. Our own messages are matched on `from_me`
+/// alone as their `number` is the recipient. Only messages of `account_q`
+/// are searched.
+pub fn find_authored_message(db: &SqliteConnection, timestamp_q: i64, author: &str, account_q: &str, groupid_q: &Option<String>) -> Option<Message> {
+    use crate::schema::messages::dsl::*;
+
+    let mut query = messages
+        .filter(account.eq(account_q.to_owned()))
+        .filter(timestamp.eq(timestamp_q))
+        .into_boxed();
+
+    if author == account_q {
+        query = query.filter(from_me.eq(true));
+    } else {
+        query = query.filter(from_me.eq(false))
+            .filter(number.eq(author.to_owned()));
+    }
+
+    match groupid_q {
+        Some(gid) => { query = query.filter(groupid.eq(gid.clone())); },
+        None => { query = query.filter(groupid.is_null()); }
+    }
+
+    query.get_result(db)
+        .ok()
+}
+
+/// Record `author`'s reaction to `target`, replacing any reaction they
+/// made before. Removing a reaction clears it.
+pub fn store_reaction(db: &SqliteConnection, target: &Message, author_q: &str, emoji_q: &str, timestamp_q: i64, remove: bool) {
+    use crate::schema::reactions::dsl::*;
+
+    let target_author = reaction_key(target);
+    let target_conversation = reaction_conversation(target);
+
+    db.transaction::<_, diesel::result::Error, _>(|| {
+        diesel::delete(reactions)
+            .filter(account.eq(&target.account))
+            .filter(conversation.eq(target_conversation))
+            .filter(message_timestamp.eq(target.timestamp))
+            .filter(message_author.eq(target_author))
+            .filter(author.eq(author_q))
+            .execute(db)?;
+
+        if !remove {
+            let reaction = NewReaction {
+                message_timestamp: target.timestamp,
+                message_author: target_author,
+                author: author_q,
+                emoji: emoji_q,
+                timestamp: timestamp_q,
+                account: &target.account,
+                conversation: target_conversation
+            };
+
+            diesel::insert_into(reactions)
+                .values(&reaction)
+                .execute(db)?;
+        }
+
+        Ok(())
+    }).expect("Couldn't store reaction");
+}
+
+/// Reactions on a message in the order they were made
+pub fn get_reactions(db: &SqliteConnection, msg: &Message) -> Vec<Reaction> {
+    use crate::schema::reactions::dsl::*;
+
+    reactions
+        .filter(account.eq(&msg.account))
+        .filter(conversation.eq(reaction_conversation(msg)))
+        .filter(message_timestamp.eq(msg.timestamp))
+        .filter(message_author.eq(reaction_key(msg)))
+        .order_by(timestamp.asc())
+        .load::<Reaction>(db)
+        .expect("Error loading reactions")
+}
+
+// Reactions name the sender of the message they target, which is the
+// message's number unless we sent it
+fn reaction_key(msg: &Message) -> &str {
+    match (msg.from_me, &msg.number) {
+        (false, Some(number)) => number,
+        _ => ""
+    }
+}
+
+// Our own messages share an author, so the conversation tells apart ones
+// sent at the same time
+fn reaction_conversation(msg: &Message) -> &str {
+    msg.groupid.as_ref()
+        .or_else(|| msg.number.as_ref())
+        .map(|key| key.as_str())
+        .unwrap_or_default()
+}
+
+/// Whether `account_q` muted notifications for the conversation with `key`
+pub fn is_muted(db: &SqliteConnection, account_q: &str, key: &str) -> bool {
+    use crate::schema::conversation_settings::dsl::*;
+
+    conversation_settings.find((account_q, key))
+        .select(muted)
+        .first(db)
+        .unwrap_or(false)
+}
+
+pub fn set_muted(db: &SqliteConnection, account_q: &str, key: &str, muted_q: bool) {
+    use crate::schema::conversation_settings::dsl::*;
+
+    diesel::replace_into(conversation_settings)
+        .values((account.eq(account_q), conversation.eq(key), muted.eq(muted_q)))
+        .execute(db)
+        .expect("Failed to store conversation settings");
+}
+
+pub fn get_setting(db: &SqliteConnection, key_q: &str) -> Option<String> {
+    use crate::schema::settings::dsl::*;
+
+    settings.find(key_q)
+        .select(value)
+        .first(db)
+        .ok()
+}
+
+pub fn set_setting(db: &SqliteConnection, key_q: &str, value_q: &str) {
+    use crate::schema::settings::dsl::*;
+
+    diesel::replace_into(settings)
+        .values((key.eq(key_q), value.eq(value_q)))
+        .execute(db)
+        .expect("Failed to store setting");
+}
+
+/// Put a failed message back to sending so it can be retried
+pub fn requeue(db: &SqliteConnection, account_q: &str, timestamp_q: i64) {
+    use crate::schema::messages::dsl::*;
+
+    let query = messages.filter(account.eq(account_q))
+        .filter(timestamp.eq(timestamp_q))
+        .filter(from_me.eq(true))
+        .filter(status.eq(MessageStatus::Failed as i32));
+
+    diesel::update(query)
+        .set(status.eq(MessageStatus::Sending as i32))
+        .execute(db)
+        .expect("Couldn't update message status");
+}
+
+/// Keep the send request for one of our messages until signald sends it
+pub fn queue_outgoing(db: &SqliteConnection, account_q: &str, timestamp_q: i64, request_q: &str) {
+    use crate::schema::outbox::dsl::*;
+
+    diesel::replace_into(outbox)
+        .values((account.eq(account_q), timestamp.eq(timestamp_q), request.eq(request_q)))
+        .execute(db)
+        .expect("Failed to queue message");
+}
+
+/// The send request for one of our unsent messages
+pub fn get_outgoing(db: &SqliteConnection, account_q: &str, timestamp_q: i64) -> Option<String> {
+    use crate::schema::outbox::dsl::*;
+
+    outbox.find((account_q, timestamp_q))
+        .select(request)
+        .first(db)
+        .ok()
+}
+
+/// Send requests of `account_q` still waiting to go out, oldest first.
+/// Failed messages stay queued but wait for a retry.
+pub fn get_outbox(db: &SqliteConnection, account_q: &str) -> Vec<(i64, String)> {
+    use crate::schema::outbox;
+
+    let sending = messages::table
+        .filter(messages::account.eq(account_q))
+        .filter(messages::from_me.eq(true))
+        .filter(messages::status.eq(MessageStatus::Sending as i32))
+        .select(messages::timestamp);
+
+    outbox::table
+        .filter(outbox::account.eq(account_q))
+        .filter(outbox::timestamp.eq_any(sending))
+        .order_by(outbox::timestamp.asc())
+        .select((outbox::timestamp, outbox::request))
+        .load(db)
+        .expect("Error loading outbox")
+}
+
+pub fn remove_outgoing(db: &SqliteConnection, account_q: &str, timestamp_q: i64) {
+    use crate::schema::outbox::dsl::*;
+
+    diesel::delete(outbox.find((account_q, timestamp_q)))
+        .execute(db)
+        .expect("Failed to remove message from outbox");
+}
+
+fn construct_message_query<'a>(account_q: &str, timestamp_q: i64, number_q: Option<String>, from_me_q: bool, groupid_q: Option<String>) -> messages::BoxedQuery<'a, Sqlite> {
+    use crate::schema::messages::dsl::*;
+
+    let mut query = messages
+        .filter(account.eq(account_q.to_owned()))
+        .filter(timestamp.eq(timestamp_q))
+        .filter(from_me.eq(from_me_q))
+        .into_boxed();
+
+    match number_q {
+        Some(number_q) => { query = query.filter(number.eq(number_q)); },
+        None => { query = query.filter(number.is_null()); }
+    }
+
+    match groupid_q {
+        Some(gid) => { query = query.filter(groupid.eq(gid)); },
+        None => { query = query.filter(groupid.is_null()); }
+    }
+
+    query
+}
+
+pub fn get_most_recent_message(db: &SqliteConnection, account_q: &str, number_q: &Option<String>, groupid_q: &Option<String>) -> Option<Message> {
+    use crate::schema::messages::dsl::*;
+    let mut query = messages
+        .filter(account.eq(account_q.to_owned()))
+        .order_by(timestamp.desc())
+        .into_boxed();
+
+    match number_q {
+        Some(number_q) => { query = query.filter(number.eq(number_q)); },
+        None => { } // Ignore number and just query by groupid
+    }
+
+    match groupid_q {
+        Some(gid) => { query = query.filter(groupid.eq(gid)); },
+        None => { query = query.filter(groupid.is_null()); }
+    }
+
+    query.get_result(db)
+        .ok()
+}
+
+pub fn get_unread(db: &SqliteConnection, account_q: &str, number_q: Option<&String>, groupid_q: Option<&String>) -> (usize, HashMap<String, Vec<i64>>) {
+    use crate::schema::messages::dsl::*;
+    let mut query = messages
+        .filter(account.eq(account_q.to_owned()))
+        .filter(is_read.eq(false))
+        .filter(from_me.eq(false))
+        .into_boxed();
+
+    match number_q {
+        Some(number_q) => { query = query.filter(number.eq(number_q)); },
+        None => { } // Ignore number and just query by groupid
+    }
+
+    match groupid_q {
+        Some(gid) => { query = query.filter(groupid.eq(gid)); },
+        None => { query = query.filter(groupid.is_null()); }
+    }
+
+    query.load::<Message>(db)
+        .expect("Error loading unread messages")
+        .drain(..)
+        .fold((0, HashMap::new()), |tup, msg| {
+            let mut map = tup.1;
+            let new_number = msg.number.unwrap();
+            if let Some(timestamps) = map.get_mut(&new_number) {
+                timestamps.push(msg.timestamp);
+            } else {
+                map.insert(new_number, vec![msg.timestamp]);
+            }
+
+            (tup.0 + 1, map)
+        })
+}
+
+/// How many messages `account_q` hasn't read yet, across conversations
+pub fn count_unread(db: &SqliteConnection, account_q: &str) -> usize {
+    use crate::schema::messages::dsl::*;
+
+    messages
+        .filter(account.eq(account_q))
+        .filter(is_read.eq(false))
+        .filter(from_me.eq(false))
+        .count()
+        .get_result::<i64>(db)
+        .expect("Error counting unread messages") as usize
+}
+
+/// Hand everything stored before accounts were told apart to `account_q`
+pub fn adopt_unowned(db: &SqliteConnection, account_q: &str) {
+    use crate::schema::{conversation_settings, messages, outbox, reactions, receipts};
+
+    db.transaction::<_, diesel::result::Error, _>(|| {
+        diesel::update(messages::table.filter(messages::account.eq("")))
+            .set(messages::account.eq(account_q))
+            .execute(db)?;
+        diesel::update(reactions::table.filter(reactions::account.eq("")))
+            .set(reactions::account.eq(account_q))
+            .execute(db)?;
+        diesel::update(receipts::table.filter(receipts::account.eq("")))
+            .set(receipts::account.eq(account_q))
+            .execute(db)?;
+        diesel::update(outbox::table.filter(outbox::account.eq("")))
+            .set(outbox::account.eq(account_q))
+            .execute(db)?;
+        diesel::update(conversation_settings::table.filter(conversation_settings::account.eq("")))
+            .set(conversation_settings::account.eq(account_q))
+            .execute(db)?;
+
+        Ok(())
+    }).expect("Couldn't adopt stored messages");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_db;
+
+    const ACCOUNT: &str = "+15550000000";
+    const CONTACT: &str = "+15551111111";
+    const OTHER_ACCOUNT: &str = "+15553333333";
+
+    fn incoming(timestamp: i64, body: &str) -> NewMessage {
+        NewMessage {
+            timestamp,
+            number: Some(CONTACT.to_owned()),
+            from_me: false,
+            is_read: false,
+            attachments: None,
+            body: body.to_owned(),
+            groupid: None,
+            quote_timestamp: None,
+            quote_author: None,
+            mentions: None,
+            mentions_start: None,
+            status: MessageStatus::Sent as i32,
+            account: ACCOUNT.to_owned()
+        }
+    }
+
+    #[test]
+    fn tracks_unread_messages() {
+        let db = test_db();
+        store_message(&db, &incoming(1, "one"));
+        store_message(&db, &incoming(2, "two"));
+
+        let (count, unread) = get_unread(&db, ACCOUNT, Some(&CONTACT.to_owned()), None);
+        assert_eq!(count, 2);
+        assert_eq!(unread[CONTACT], vec![1, 2]);
+
+        read_msgs(&db, ACCOUNT, &vec![1, 2], &CONTACT.to_owned());
+        let (count, _) = get_unread(&db, ACCOUNT, Some(&CONTACT.to_owned()), None);
+        assert_eq!(count, 0);
+    }
+
+    fn reactions(db: &SqliteConnection, target: &Message) -> Vec<(String, String)> {
+        get_reactions(db, target).drain(..).map(|reaction| {
+            (reaction.emoji, reaction.author)
+        }).collect()
+    }
+
+    #[test]
+    fn replaces_and_removes_reactions() {
+        let db = test_db();
+        store_message(&db, &incoming(1, "react to me"));
+        let groupid = None;
+
+        let target = find_authored_message(&db, 1, CONTACT, ACCOUNT, &groupid).unwrap();
+        store_reaction(&db, &target, CONTACT, "👍", 10, false);
+        store_reaction(&db, &target, ACCOUNT, "❤️", 11, false);
+        store_reaction(&db, &target, CONTACT, "😂", 12, false);
+        assert_eq!(reactions(&db, &target), vec![
+            ("❤️".to_owned(), ACCOUNT.to_owned()),
+            ("😂".to_owned(), CONTACT.to_owned())
+        ]);
+
+        store_reaction(&db, &target, ACCOUNT, "❤️", 13, true);
+        assert_eq!(reactions(&db, &target), vec![("😂".to_owned(), CONTACT.to_owned())]);
+
+        assert!(find_authored_message(&db, 2, CONTACT, ACCOUNT, &groupid).is_none());
+    }
+
+    #[test]
+    fn keeps_reactions_to_own_messages_apart() {
+        let db = test_db();
+        let other_contact = "+15552222222";
+        store_message(&db, &outgoing(1));
+        store_message(&db, &NewMessage {
+            number: Some(other_contact.to_owned()),
+            ..outgoing(1)
+        });
+
+        let first = get_message(&db, ACCOUNT, 1, Some(CONTACT.to_owned()), true, None);
+        let second = get_message(&db, ACCOUNT, 1, Some(other_contact.to_owned()), true, None);
+        store_reaction(&db, &first, CONTACT, "👍", 10, false);
+        store_reaction(&db, &second, other_contact, "😂", 11, false);
+
+        assert_eq!(reactions(&db, &first), vec![("👍".to_owned(), CONTACT.to_owned())]);
+        assert_eq!(reactions(&db, &second), vec![("😂".to_owned(), other_contact.to_owned())]);
+    }
+
+    #[test]
+    fn migrates_reaction_columns() {
+        use diesel::connection::SimpleConnection;
+        use crate::test_support::{MIGRATIONS, REACTIONS_MIGRATION};
+
+        let db = SqliteConnection::establish(":memory:").unwrap();
+        for migration in MIGRATIONS.iter().take(REACTIONS_MIGRATION) {
+            db.batch_execute(migration).unwrap();
+        }
+
+        db.batch_execute(&format!(
+            "INSERT INTO messages (timestamp, number, from_me, is_read, body, reaction_emojis, reaction_authors) \
+             VALUES (1, '{contact}', 0, 1, 'hi', '👍\n😂\n', '{account}\n{contact}\n'), \
+                    (2, '{contact}', 1, 1, 'hello', '❤️\n', '{contact}\n'), \
+                    (3, '{contact}', 0, 1, 'plain', NULL, NULL);",
+            contact = CONTACT,
+            account = ACCOUNT
+        )).unwrap();
+        for migration in MIGRATIONS.iter().skip(REACTIONS_MIGRATION) {
+            db.batch_execute(migration).unwrap();
+        }
+        adopt_unowned(&db, ACCOUNT);
+
+        let incoming = get_message(&db, ACCOUNT, 1, Some(CONTACT.to_owned()), false, None);
+        let mut emojis: Vec<(String, String)> = reactions(&db, &incoming);
+        emojis.sort();
+        assert_eq!(emojis, vec![
+            ("👍".to_owned(), ACCOUNT.to_owned()),
+            ("😂".to_owned(), CONTACT.to_owned())
+        ]);
+
+        let outgoing = get_message(&db, ACCOUNT, 2, Some(CONTACT.to_owned()), true, None);
+        assert_eq!(reactions(&db, &outgoing), vec![("❤️".to_owned(), CONTACT.to_owned())]);
+        // Read before receipts were tracked
+        assert_eq!(outgoing.status(), MessageStatus::Read);
+
+        let plain = get_message(&db, ACCOUNT, 3, Some(CONTACT.to_owned()), false, None);
+        assert!(reactions(&db, &plain).is_empty());
+    }
+
+    fn outgoing(timestamp: i64) -> NewMessage {
+        NewMessage {
+            from_me: true,
+            status: MessageStatus::Sending as i32,
+            ..incoming(timestamp, "sent")
+        }
+    }
+
+    fn status_of(db: &SqliteConnection, timestamp: i64) -> MessageStatus {
+        get_message(db, ACCOUNT, timestamp, Some(CONTACT.to_owned()), true, None).status()
+    }
+
+    #[test]
+    fn tracks_receipts() {
+        let db = test_db();
+        store_message(&db, &outgoing(1));
+        store_message(&db, &outgoing(2));
+
+        // Receipts can arrive before signald answers the send
+        assert_eq!(store_receipt(&db, ACCOUNT, &[1], CONTACT, MessageStatus::Delivered, 10).len(), 1);
+        set_status(&db, ACCOUNT, 1, MessageStatus::Sent);
+        set_status(&db, ACCOUNT, 2, MessageStatus::Failed);
+        assert_eq!(status_of(&db, 1), MessageStatus::Delivered);
+        assert_eq!(status_of(&db, 2), MessageStatus::Failed);
+
+        store_receipt(&db, ACCOUNT, &[1, 2], CONTACT, MessageStatus::Read, 20);
+        assert!(store_receipt(&db, ACCOUNT, &[1], CONTACT, MessageStatus::Delivered, 30).is_empty());
+        assert_eq!(status_of(&db, 1), MessageStatus::Read);
+        assert_eq!(status_of(&db, 2), MessageStatus::Read);
+
+        let msg = get_message(&db, ACCOUNT, 1, Some(CONTACT.to_owned()), true, None);
+        assert!(msg.is_read);
+        let receipts = get_receipts(&db, &msg);
+        assert_eq!(receipts.len(), 1);
+        assert_eq!((receipts[0].status(), receipts[0].timestamp), (MessageStatus::Read, 20));
+
+        // Nothing to update for messages we don't have
+        assert!(store_receipt(&db, ACCOUNT, &[3], CONTACT, MessageStatus::Read, 40).is_empty());
+    }
+
+    #[test]
+    fn stores_settings() {
+        let db = test_db();
+        assert_eq!(get_setting(&db, "theme"), None);
+
+        set_setting(&db, "theme", "dark");
+        set_setting(&db, "theme", "light");
+        assert_eq!(get_setting(&db, "theme").as_deref(), Some("light"));
+    }
+
+    #[test]
+    fn finds_most_recent_message() {
+        let db = test_db();
+        store_message(&db, &incoming(5, "later"));
+        store_message(&db, &incoming(3, "earlier"));
+
+        let msg = get_most_recent_message(&db, ACCOUNT, &Some(CONTACT.to_owned()), &None).unwrap();
+        assert_eq!(msg.body, "later");
+    }
+
+    #[test]
+    fn keeps_accounts_apart() {
+        let db = test_db();
+        store_message(&db, &incoming(1, "mine"));
+        store_message(&db, &NewMessage {
+            account: OTHER_ACCOUNT.to_owned(),
+            ..incoming(1, "theirs")
+        });
+        store_message(&db, &NewMessage {
+            account: OTHER_ACCOUNT.to_owned(),
+            ..incoming(2, "theirs again")
+        });
+
+        let msg = get_message(&db, ACCOUNT, 1, Some(CONTACT.to_owned()), false, None);
+        assert_eq!(msg.body, "mine");
+        assert_eq!(count_unread(&db, ACCOUNT), 1);
+        assert_eq!(count_unread(&db, OTHER_ACCOUNT), 2);
+
+        read_msgs(&db, OTHER_ACCOUNT, &vec![1], &CONTACT.to_owned());
+        assert_eq!(count_unread(&db, ACCOUNT), 1);
+        assert_eq!(count_unread(&db, OTHER_ACCOUNT), 1);
+
+        let target = find_authored_message(&db, 1, CONTACT, OTHER_ACCOUNT, &None).unwrap();
+        store_reaction(&db, &target, OTHER_ACCOUNT, "👍", 10, false);
+        assert!(get_reactions(&db, &msg).is_empty());
+        assert_eq!(get_reactions(&db, &target).len(), 1);
+
+        set_muted(&db, OTHER_ACCOUNT, CONTACT, true);
+        assert!(is_muted(&db, OTHER_ACCOUNT, CONTACT));
+        assert!(!is_muted(&db, ACCOUNT, CONTACT));
+    }
+}
@@ -0,0 +1,83 @@
+use std::fmt;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Clone)]
+pub enum Error {
+    /// Couldn't reach signald, or the socket went away mid-request
+    Socket(String),
+    /// signald answered with something we don't understand
+    Protocol(String),
+    /// Signal's servers are rate limiting this account
+    RateLimited(String),
+    /// A contact's safety number changed and hasn't been trusted yet
+    UntrustedIdentity(String),
+    /// The recipient isn't registered with Signal
+    UnregisteredUser(String),
+    /// Registering needs a captcha token solved first
+    CaptchaRequired(String),
+    /// The number has a registration lock, verifying needs its PIN
+    RegistrationLocked(String),
+    /// A registered or linked account couldn't be handed over to the app
+    AccountSetup,
+    /// What was given to link a new device isn't a linking URI
+    InvalidLinkingUri(String),
+    /// An attachment is too big for signald to send
+    AttachmentTooLarge(String),
+    /// Any other error reported by signald
+    Signald(String),
+    /// signald didn't answer the request in time
+    Timeout(&'static str),
+    /// The request was cancelled before signald answered
+    Cancelled
+}
+
+impl Error {
+    /// Classify an error returned by a signald call. signald reports
+    /// failures by error type name, which signald-rs passes along in the
+    /// message.
+    pub fn from_signald(message: String) -> Self {
+        if message.contains("RateLimit") {
+            Error::RateLimited(message)
+        } else if message.contains("UntrustedIdentity") {
+            Error::UntrustedIdentity(message)
+        } else if message.contains("UnregisteredUser") {
+            Error::UnregisteredUser(message)
+        } else if message.contains("Captcha") {
+            Error::CaptchaRequired(message)
+        } else if message.contains("AccountLocked") || message.contains("RegistrationLock") {
+            Error::RegistrationLocked(message)
+        } else if message.contains("InvalidRequest") {
+            Error::Protocol(message)
+        } else {
+            Error::Signald(message)
+        }
+    }
+
+    /// signald answered `key` with a different type than requested
+    pub fn unexpected(key: &str) -> Self {
+        Error::Protocol(format!("unexpected response type for {}", key))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Socket(e) => write!(f, "Lost connection to signald: {}", e),
+            Error::Protocol(e) => write!(f, "Unexpected response from signald: {}", e),
+            Error::RateLimited(_) => write!(f, "Rate limited by Signal, try again later"),
+            Error::UntrustedIdentity(_) => write!(f, "Safety number changed, verify it before sending"),
+            Error::UnregisteredUser(_) => write!(f, "Recipient isn't registered with Signal"),
+            Error::CaptchaRequired(_) => write!(f, "Signal wants a captcha solved before registering"),
+            Error::RegistrationLocked(_) => write!(f, "This number has a registration lock, enter its PIN"),
+            Error::AccountSetup => write!(f, "Couldn't finish setting up the account"),
+            Error::InvalidLinkingUri(_) => write!(f, "That isn't a code for linking a device"),
+            Error::AttachmentTooLarge(path) => write!(f, "{} is too big to send", path),
+            Error::Signald(e) => write!(f, "signald error: {}", e),
+            Error::Timeout(key) => write!(f, "signald took too long to answer {}", key),
+            Error::Cancelled => write!(f, "Request cancelled")
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,27 @@
+//! Everything signal-rs does that doesn't involve drawing it: talking to
+//! signald, storing messages and keeping track of conversations. Frontends
+//! drive a `Client` and react to the `Event`s it produces.
+
+#[macro_use]
+extern crate diesel;
+extern crate dotenv;
+
+pub mod attachments;
+pub mod blurhash;
+pub mod client;
+pub mod conversation;
+pub mod database;
+pub mod error;
+pub mod models;
+pub mod notifications;
+pub mod phone;
+pub mod schema;
+pub mod settings;
+pub mod signal_type_utils;
+pub mod signald_bridge;
+#[cfg(test)]
+mod test_support;
+
+pub use client::{Client, Event, OutgoingAttachment};
+pub use conversation::{Conversation, ConversationType};
+pub use error::{Error, Result};
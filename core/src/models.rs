@@ -0,0 +1,139 @@
+use super::schema::{attachments, messages, reactions, receipts};
+
+#[derive(Queryable, Default, Clone)]
+pub struct Message {
+    pub timestamp: i64,
+    pub number: Option<String>,
+    pub from_me: bool,
+    pub is_read: bool,
+    pub attachments: Option<String>,
+    pub body: String,
+    pub groupid: Option<String>,
+    pub quote_timestamp: Option<i64>,
+    pub quote_author: Option<String>,
+    pub mentions: Option<Vec<u8>>,
+    pub mentions_start: Option<Vec<u8>>,
+    /// A `MessageStatus`, only meaningful for our own messages
+    pub status: i32,
+    /// Which of our accounts sent or received it
+    pub account: String
+}
+
+#[derive(Insertable)]
+#[table_name = "messages"]
+pub struct NewMessage {
+    pub timestamp: i64,
+    pub number: Option<String>,
+    pub from_me: bool,
+    pub is_read: bool,
+    pub attachments: Option<String>,
+    pub body: String,
+    pub groupid: Option<String>,
+    pub quote_timestamp: Option<i64>,
+    pub quote_author: Option<String>,
+    pub mentions: Option<Vec<u8>>,
+    pub mentions_start: Option<Vec<u8>>,
+    /// A `MessageStatus`, only meaningful for our own messages
+    pub status: i32,
+    /// Which of our accounts sent or received it
+    pub account: String
+}
+
+impl Message {
+    pub fn status(&self) -> MessageStatus {
+        MessageStatus::from(self.status)
+    }
+}
+
+/// How far one of our messages got. Receipts only ever move a message
+/// forward, so later states compare greater.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MessageStatus {
+    Failed = -1,
+    Sending = 0,
+    Sent = 1,
+    Delivered = 2,
+    Read = 3
+}
+
+impl From<i32> for MessageStatus {
+    fn from(status: i32) -> Self {
+        match status {
+            -1 => MessageStatus::Failed,
+            0 => MessageStatus::Sending,
+            2 => MessageStatus::Delivered,
+            3 => MessageStatus::Read,
+            _ => MessageStatus::Sent
+        }
+    }
+}
+
+#[derive(Queryable)]
+pub struct Attachment {
+    pub id: String,
+    pub blurhash: Option<String>,
+    pub content_type: String,
+    pub filename: Option<String>,
+    pub width: Option<i32>,
+    pub height: Option<i32>
+}
+
+#[derive(Insertable)]
+#[table_name = "attachments"]
+pub struct NewAttachment<'a> {
+    pub id: &'a str,
+    pub blurhash: Option<&'a str>,
+    pub content_type: &'a str,
+    pub filename: Option<&'a str>,
+    pub width: Option<i32>,
+    pub height: Option<i32>
+}
+
+#[derive(Queryable, Clone)]
+pub struct Reaction {
+    pub message_timestamp: i64,
+    pub message_author: String,
+    pub author: String,
+    pub emoji: String,
+    pub timestamp: i64,
+    pub account: String,
+    pub conversation: String
+}
+
+#[derive(Insertable)]
+#[table_name = "reactions"]
+pub struct NewReaction<'a> {
+    pub message_timestamp: i64,
+    pub message_author: &'a str,
+    pub author: &'a str,
+    pub emoji: &'a str,
+    pub timestamp: i64,
+    pub account: &'a str,
+    pub conversation: &'a str
+}
+
+/// The furthest one recipient got with one of our messages
+#[derive(Queryable, Clone)]
+pub struct Receipt {
+    pub message_timestamp: i64,
+    pub member: String,
+    pub status: i32,
+    pub timestamp: i64,
+    pub account: String
+}
+
+impl Receipt {
+    pub fn status(&self) -> MessageStatus {
+        MessageStatus::from(self.status)
+    }
+}
+
+#[derive(Insertable)]
+#[table_name = "receipts"]
+pub struct NewReceipt<'a> {
+    pub message_timestamp: i64,
+    pub member: &'a str,
+    pub status: i32,
+    pub timestamp: i64,
+    pub account: &'a str
+}
@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::models::{Message, NewMessage};
+
+pub struct Reaction {
+    /// The account whose message it is
+    pub account: String,
+    pub emoji: String,
+    pub author: String,
+    /// Whether the author took this reaction back
+    pub remove: bool,
+    // Identify the message reacted to
+    pub message_timestamp: i64,
+    pub message_number: Option<String>,
+    pub message_from_me: bool,
+    pub groupid: Option<String>
+}
+
+/// Someone started or stopped typing in a conversation
+pub struct Typing {
+    /// The account being typed to
+    pub account: String,
+    pub number: String,
+    pub groupid: Option<String>,
+    pub started: bool
+}
+
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    Reconnecting {
+        attempt: u32,
+        retry_in: Duration
+    }
+}
+
+pub enum Notification {
+    NewMessage(NewMessage),
+    Reaction(Reaction),
+    Typing(Typing),
+    /// Our messages a delivery or read receipt moved forward
+    Receipt(Vec<Message>),
+    /// Messages `account` read on another device, timestamps by sender
+    Read {
+        account: String,
+        read: HashMap<String, Vec<i64>>
+    },
+    Connection(ConnectionState)
+}
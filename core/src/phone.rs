@@ -0,0 +1,110 @@
+//! Phone numbers the way Signal wants them, in E.164: a plus, the country
+//! calling code, then the national number, 15 digits at most
+
+/// Countries for the registration picker with their calling codes
+pub const COUNTRIES: &[(&str, &str)] = &[
+    ("Argentina", "54"),
+    ("Australia", "61"),
+    ("Austria", "43"),
+    ("Belgium", "32"),
+    ("Brazil", "55"),
+    ("Canada", "1"),
+    ("Chile", "56"),
+    ("China", "86"),
+    ("Colombia", "57"),
+    ("Czechia", "420"),
+    ("Denmark", "45"),
+    ("Egypt", "20"),
+    ("Finland", "358"),
+    ("France", "33"),
+    ("Germany", "49"),
+    ("Greece", "30"),
+    ("Hong Kong", "852"),
+    ("Hungary", "36"),
+    ("India", "91"),
+    ("Indonesia", "62"),
+    ("Ireland", "353"),
+    ("Israel", "972"),
+    ("Italy", "39"),
+    ("Japan", "81"),
+    ("Kenya", "254"),
+    ("Mexico", "52"),
+    ("Netherlands", "31"),
+    ("New Zealand", "64"),
+    ("Nigeria", "234"),
+    ("Norway", "47"),
+    ("Pakistan", "92"),
+    ("Philippines", "63"),
+    ("Poland", "48"),
+    ("Portugal", "351"),
+    ("Romania", "40"),
+    ("Singapore", "65"),
+    ("South Africa", "27"),
+    ("South Korea", "82"),
+    ("Spain", "34"),
+    ("Sweden", "46"),
+    ("Switzerland", "41"),
+    ("Taiwan", "886"),
+    ("Turkey", "90"),
+    ("Ukraine", "380"),
+    ("United Kingdom", "44"),
+    ("United States", "1")
+];
+
+/// Turn a number as typed into E.164. A leading `+` or `00` means it
+/// already has a country code, otherwise `calling_code` is put in front
+/// and a national trunk prefix `0` dropped. `None` if it can't be a valid
+/// number.
+pub fn to_e164(calling_code: &str, input: &str) -> Option<String> {
+    let input: String = input.chars()
+        .filter(|c| !c.is_whitespace() && !"-.()/".contains(*c))
+        .collect();
+
+    let digits = if let Some(international) = input.strip_prefix('+') {
+        international.to_owned()
+    } else if let Some(international) = input.strip_prefix("00") {
+        international.to_owned()
+    } else {
+        // Italian numbers keep their leading 0
+        let national = match calling_code {
+            "39" => input.as_str(),
+            _ => input.strip_prefix('0').unwrap_or(&input)
+        };
+        format!("{}{}", calling_code, national)
+    };
+
+    let valid = (8..=15).contains(&digits.len())
+        && digits.chars().all(|c| c.is_ascii_digit())
+        && !digits.starts_with('0');
+
+    if valid {
+        Some(format!("+{}", digits))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_numbers_as_e164() {
+        assert_eq!(to_e164("1", "(555) 111-1111").as_deref(), Some("+15551111111"));
+        assert_eq!(to_e164("44", "07700 900123").as_deref(), Some("+447700900123"));
+        assert_eq!(to_e164("39", "06 1234 5678").as_deref(), Some("+390612345678"));
+
+        // Numbers with their own country code ignore the picker
+        assert_eq!(to_e164("1", "+49 30 123456").as_deref(), Some("+4930123456"));
+        assert_eq!(to_e164("1", "0049 30 123456").as_deref(), Some("+4930123456"));
+    }
+
+    #[test]
+    fn rejects_invalid_numbers() {
+        assert_eq!(to_e164("1", ""), None);
+        assert_eq!(to_e164("1", "555-CALL"), None);
+        assert_eq!(to_e164("1", "12345"), None);
+        assert_eq!(to_e164("1", "+1234567890123456"), None);
+        assert_eq!(to_e164("1", "+0123456789"), None);
+    }
+}
@@ -0,0 +1,83 @@
+table! {
+    attachments (id) {
+        id -> Text,
+        blurhash -> Nullable<Text>,
+        content_type -> Text,
+        filename -> Nullable<Text>,
+        width -> Nullable<Integer>,
+        height -> Nullable<Integer>,
+    }
+}
+
+table! {
+    conversation_settings (account, conversation) {
+        conversation -> Text,
+        muted -> Bool,
+        account -> Text,
+    }
+}
+
+table! {
+    messages (account, timestamp, number, from_me, groupid) {
+        timestamp -> BigInt,
+        number -> Nullable<Text>,
+        from_me -> Bool,
+        is_read -> Bool,
+        attachments -> Nullable<Text>,
+        body -> Text,
+        groupid -> Nullable<Text>,
+        quote_timestamp -> Nullable<BigInt>,
+        quote_author -> Nullable<Text>,
+        mentions -> Nullable<Binary>,
+        mentions_start -> Nullable<Binary>,
+        status -> Integer,
+        account -> Text,
+    }
+}
+
+table! {
+    outbox (account, timestamp) {
+        timestamp -> BigInt,
+        request -> Text,
+        account -> Text,
+    }
+}
+
+table! {
+    reactions (account, conversation, message_timestamp, message_author, author) {
+        message_timestamp -> BigInt,
+        message_author -> Text,
+        author -> Text,
+        emoji -> Text,
+        timestamp -> BigInt,
+        account -> Text,
+        conversation -> Text,
+    }
+}
+
+table! {
+    receipts (account, message_timestamp, member) {
+        message_timestamp -> BigInt,
+        member -> Text,
+        status -> Integer,
+        timestamp -> BigInt,
+        account -> Text,
+    }
+}
+
+table! {
+    settings (key) {
+        key -> Text,
+        value -> Text,
+    }
+}
+
+allow_tables_to_appear_in_same_query!(
+    attachments,
+    conversation_settings,
+    messages,
+    outbox,
+    reactions,
+    receipts,
+    settings,
+);
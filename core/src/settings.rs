@@ -0,0 +1,10 @@
+//! Keys for preferences kept in the `settings` table
+
+/// Keep receiving messages with the window closed
+pub const RUN_IN_BACKGROUND: &str = "run_in_background";
+/// Start in the background when logging in
+pub const START_ON_LOGIN: &str = "start_on_login";
+/// Let contacts see when we're typing
+pub const SEND_TYPING: &str = "send_typing";
+/// The account shown when starting up
+pub const ACTIVE_ACCOUNT: &str = "active_account";
@@ -0,0 +1,1019 @@
+use signald::Signald;
+use signald::types::{IncomingMessageV1, JsonDataMessageV1, JsonReadMessage, JsonSyncMessageV1, ListAccountsRequestV1,
+                     SignaldTypes, SubscribeRequestV1};
+use async_std::channel::{unbounded, Receiver, Sender};
+use async_std::future::timeout;
+use uuid::Uuid;
+use diesel::sqlite::SqliteConnection;
+use dotenv::dotenv;
+use std::env;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::database;
+use crate::error::{Error, Result};
+use crate::models::{MessageStatus, NewMessage};
+use crate::notifications::{ConnectionState, Notification, Reaction, Typing};
+use crate::signal_type_utils::*;
+
+const DEFAULT_SOCKET_PATHS: [&str; 2] = [
+    "$XDG_RUNTIME_DIR/signald/signald.sock",
+    "/var/run/signald/signald.sock"
+];
+
+// How often to check signald is still there while nothing's happening
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+const MIN_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(15);
+// How long signald gets to show it's still there
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+// Requests which legitimately take longer than most
+const SLOW_REQUESTS: [(&str, Duration); 3] = [
+    ("send", Duration::from_secs(60)),
+    // Only answers once the QR code has been scanned
+    ("finish_link", Duration::from_secs(300)),
+    ("request_sync", Duration::from_secs(60))
+];
+
+pub type SignaldResponse = Result<SignaldTypes>;
+
+pub struct SignaldInteraction {
+    pub key: &'static str,
+    pub msg: SignaldTypes,
+    /// How long the bridge waits for signald before giving up on it
+    pub timeout: Duration,
+    pub response_channel: Option<Sender<SignaldResponse>>
+}
+
+/// Resolves a pending request early with `Error::Cancelled`
+#[derive(Clone)]
+pub struct CancelHandle {
+    sender: Sender<SignaldResponse>
+}
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        // Closing the channel also tells the bridge to skip the request if
+        // it hasn't been sent to signald yet
+        self.sender.try_send(Err(Error::Cancelled)).ok();
+        self.sender.close();
+    }
+}
+
+/// A request handed to the bridge whose response hasn't been awaited yet
+pub struct PendingRequest {
+    key: &'static str,
+    timeout: Duration,
+    receiver: Receiver<SignaldResponse>,
+    handle: CancelHandle
+}
+
+impl PendingRequest {
+    pub fn new(key: &'static str, timeout: Duration) -> (Self, Sender<SignaldResponse>) {
+        let (sender, receiver) = async_std::channel::bounded(1);
+        let request = PendingRequest {
+            key,
+            timeout,
+            receiver,
+            handle: CancelHandle {
+                sender: sender.clone()
+            }
+        };
+
+        (request, sender)
+    }
+
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.handle.clone()
+    }
+
+    pub async fn response(self) -> Result<SignaldTypes> {
+        match timeout(self.timeout, self.receiver.recv()).await {
+            Ok(response) => response.map_err(|_| {
+                Error::Socket("signald bridge stopped".to_owned())
+            })?,
+            Err(_) => Err(Error::Timeout(self.key))
+        }
+    }
+}
+
+/// How long to wait for signald to answer each type of request
+pub struct Timeouts {
+    default: Duration,
+    per_key: HashMap<String, Duration>
+}
+
+impl Timeouts {
+    pub fn get(&self, key: &str) -> Duration {
+        self.per_key.get(key).copied().unwrap_or(self.default)
+    }
+
+    pub fn set(&mut self, key: &str, timeout: Duration) {
+        self.per_key.insert(key.to_owned(), timeout);
+    }
+
+    pub fn set_default(&mut self, timeout: Duration) {
+        self.default = timeout;
+    }
+
+    /// Defaults, overridden by `SIGNALD_TIMEOUT` for all requests and
+    /// `SIGNALD_TIMEOUT_<KEY>` for individual request types (in seconds)
+    pub fn from_env() -> Self {
+        dotenv().ok();
+
+        let mut timeouts = Timeouts::default();
+        for (var, secs) in env::vars() {
+            let timeout = match secs.parse() {
+                Ok(secs) => Duration::from_secs(secs),
+                Err(_) => continue
+            };
+
+            if var == "SIGNALD_TIMEOUT" {
+                timeouts.set_default(timeout);
+            } else if let Some(key) = var.strip_prefix("SIGNALD_TIMEOUT_") {
+                timeouts.set(&key.to_lowercase(), timeout);
+            }
+        }
+
+        timeouts
+    }
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            default: DEFAULT_TIMEOUT,
+            per_key: SLOW_REQUESTS.iter().map(|&(key, timeout)| {
+                (key.to_owned(), timeout)
+            }).collect()
+        }
+    }
+}
+
+pub struct BridgeConfig {
+    pub socket_paths: Vec<String>,
+    /// How often to check the socket when it's otherwise idle
+    pub keepalive: Duration
+}
+
+impl BridgeConfig {
+    /// Use the socket set by `SIGNALD_SOCKET` if present, otherwise try
+    /// signald's default locations in order
+    pub fn from_env() -> Self {
+        dotenv().ok();
+
+        let socket_paths = match env::var("SIGNALD_SOCKET") {
+            Ok(path) => expand_env_vars(&path).into_iter().collect(),
+            Err(_) => DEFAULT_SOCKET_PATHS.iter().filter_map(|path| {
+                expand_env_vars(path)
+            }).collect()
+        };
+
+        BridgeConfig {
+            socket_paths,
+            keepalive: KEEPALIVE_INTERVAL
+        }
+    }
+}
+
+/// Expand `$VAR` and `${VAR}` references in a path. Returns `None` if
+/// any referenced variable is unset, as the path can't be valid then.
+pub fn expand_env_vars(path: &str) -> Option<String> {
+    let mut expanded = String::with_capacity(path.len());
+    let mut chars = path.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            expanded.push(c);
+            continue;
+        }
+
+        let braced = chars.peek() == Some(&'{');
+        if braced {
+            chars.next();
+        }
+
+        let mut name = String::new();
+        while let Some(&c) = chars.peek() {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                name.push(c);
+                chars.next();
+            } else {
+                break;
+            }
+        }
+
+        if braced && chars.next() != Some('}') {
+            return None;
+        }
+
+        if name.is_empty() {
+            expanded.push('$');
+        } else {
+            expanded.push_str(&env::var(name).ok()?);
+        }
+    }
+
+    Some(expanded)
+}
+
+struct Connection {
+    signald: Signald
+}
+
+impl Connection {
+    /// signald-rs doesn't tell us when the socket goes away, so ask signald
+    /// something cheap over it
+    async fn is_alive(&self) -> bool {
+        let ping = self.signald.remote_call(
+            "list_accounts",
+            Uuid::new_v4(),
+            SignaldTypes::ListAccountsRequestV1(ListAccountsRequestV1::default())
+        );
+
+        matches!(timeout(PING_TIMEOUT, ping).await, Ok(Ok(_)))
+    }
+}
+
+/// What the bridge's main loop acts on
+enum Command {
+    // Boxed as requests are much bigger than anything else here
+    Request(Box<SignaldInteraction>),
+    /// A call went wrong, see whether the socket is still there
+    Check,
+    /// The socket went away, reconnect unless that already happened
+    Lost(Arc<Connection>)
+}
+
+/// Keep trying every configured socket with exponential backoff until
+/// signald answers
+async fn connect(config: &BridgeConfig, db: Arc<Mutex<SqliteConnection>>, sender: Sender<Notification>) -> Connection {
+    let mut attempt = 0;
+    let mut backoff = MIN_BACKOFF;
+
+    loop {
+        for path in config.socket_paths.iter() {
+            let handler_sender = sender.clone();
+            let db = db.clone();
+            let signald = Signald::connect(
+                path.as_str(),
+                move |msg| {
+                    // Use async std runtime to manage future as that's what's being used
+                    // by the socket. This also keeps messages being stored whatever the
+                    // frontend is doing, e.g. running in the background without a window
+                    let db = db.clone();
+                    let sender = handler_sender.clone();
+                    async_std::task::spawn(async move {
+                        message_handler(db, msg, sender).await;
+                    });
+                }
+            ).await;
+
+            if let Ok(signald) = signald {
+                sender.send(Notification::Connection(ConnectionState::Connected))
+                    .await.expect("Failed to send notification");
+
+                return Connection {
+                    signald
+                };
+            }
+        }
+
+        attempt += 1;
+        sender.send(
+            Notification::Connection(
+                ConnectionState::Reconnecting {
+                    attempt,
+                    retry_in: backoff
+                }
+            )
+        ).await.expect("Failed to send notification");
+
+        async_std::task::sleep(backoff).await;
+        backoff = std::cmp::min(backoff * 2, MAX_BACKOFF);
+    }
+}
+
+/// signald forgets subscriptions along with the socket, so re-issue them
+/// after every reconnect
+async fn resubscribe(connection: &Connection, subscriptions: &[String]) {
+    for account in subscriptions {
+        let response = timeout(DEFAULT_TIMEOUT, connection.signald.remote_call(
+            "subscribe",
+            Uuid::new_v4(),
+            SignaldTypes::SubscribeRequestV1(
+                SubscribeRequestV1 {
+                    account: Some(account.clone())
+                }
+            )
+        )).await;
+
+        match response {
+            Ok(Ok(_)) => {},
+            Ok(Err(e)) => log::warn!("Failed to resubscribe {}: {}", account, e),
+            Err(_) => log::warn!("Failed to resubscribe {}: timed out", account)
+        }
+    }
+}
+
+pub async fn listen(config: BridgeConfig, db: Arc<Mutex<SqliteConnection>>, receiver: Receiver<SignaldInteraction>, sender: Sender<Notification>) {
+    sender.send(Notification::Connection(ConnectionState::Connecting))
+        .await.expect("Failed to send notification");
+
+    let mut connection = Arc::new(connect(&config, db.clone(), sender.clone()).await);
+    let mut subscriptions: Vec<String> = Vec::new();
+
+    let (commands, command_receiver) = unbounded();
+    async_std::task::spawn(forward_requests(receiver, commands.clone()));
+    async_std::task::spawn(keepalive(config.keepalive, commands.clone()));
+
+    while let Ok(command) = command_receiver.recv().await {
+        match command {
+            Command::Request(request) => {
+                // Cancelled or timed out before we got to it
+                if request.response_channel.as_ref().map_or(false, |sender| sender.is_closed()) {
+                    continue;
+                }
+
+                if let SignaldTypes::SubscribeRequestV1(subscribe) = &request.msg {
+                    if let Some(account) = &subscribe.account {
+                        if !subscriptions.contains(account) {
+                            subscriptions.push(account.clone());
+                        }
+                    }
+                }
+
+                // Some requests take minutes, e.g. finish_link, so don't
+                // hold the others up behind them
+                async_std::task::spawn(call(connection.clone(), request, commands.clone()));
+            },
+            // Pinged on the side, a slow signald mustn't hold up requests
+            Command::Check => {
+                async_std::task::spawn(check(connection.clone(), commands.clone()));
+            },
+            Command::Lost(lost) => {
+                if Arc::ptr_eq(&lost, &connection) {
+                    sender.send(Notification::Connection(ConnectionState::Connecting))
+                        .await.expect("Failed to send notification");
+                    connection = Arc::new(connect(&config, db.clone(), sender.clone()).await);
+                    resubscribe(&connection, &subscriptions).await;
+                }
+            }
+        }
+    }
+}
+
+// Stops the bridge along with the client by closing the command channel
+async fn forward_requests(receiver: Receiver<SignaldInteraction>, commands: Sender<Command>) {
+    while let Ok(request) = receiver.recv().await {
+        if commands.send(Command::Request(Box::new(request))).await.is_err() {
+            return;
+        }
+    }
+
+    commands.close();
+}
+
+// signald-rs doesn't say when the socket closes, e.g. when signald restarts,
+// so check on it every so often even if nothing's being asked of it
+async fn keepalive(interval: Duration, commands: Sender<Command>) {
+    loop {
+        async_std::task::sleep(interval).await;
+
+        if commands.send(Command::Check).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn check(connection: Arc<Connection>, commands: Sender<Command>) {
+    if !connection.is_alive().await {
+        commands.send(Command::Lost(connection)).await.ok();
+    }
+}
+
+async fn call(connection: Arc<Connection>, request: Box<SignaldInteraction>, commands: Sender<Command>) {
+    let response = timeout(request.timeout, connection.signald.remote_call(
+        request.key,
+        Uuid::new_v4(),
+        request.msg
+    )).await;
+
+    let response = match response {
+        Ok(Ok(response)) => Ok(response),
+        // signald-rs reports signald's errors and a dead socket alike
+        Ok(Err(e)) => if connection.is_alive().await {
+            Err(Error::from_signald(e.to_string()))
+        } else {
+            commands.send(Command::Lost(connection)).await.ok();
+            Err(Error::Socket(e.to_string()))
+        },
+        Err(_) => {
+            commands.send(Command::Check).await.ok();
+            Err(Error::Timeout(request.key))
+        }
+    };
+
+    if let Some(sender) = request.response_channel {
+        // Fails if the request was cancelled or timed out in the meantime
+        sender.try_send(response).ok();
+    } else if let Err(e) = response {
+        log::warn!("{} failed: {}", request.key, e);
+    }
+}
+
+// Every subscribed account's envelopes come through here, tagged with the
+// account they were sent to
+async fn message_handler(db: Arc<Mutex<SqliteConnection>>, msg: IncomingMessageV1, sender: Sender<Notification>) {
+    let account = match &msg.account {
+        Some(account) => account.clone(),
+        None => return
+    };
+
+    if msg.data_message.is_some() {
+        handle_data_msg(db.clone(), account.clone(), msg.clone(), sender.clone()).await;
+    } 
+    if msg.typing_message.is_some() {
+        handle_typing(account.clone(), msg.clone(), sender.clone()).await;
+    }
+    if msg.receipt_message.is_some() {
+        handle_receipt(db.clone(), account.clone(), msg.clone(), sender.clone()).await;
+    }
+    if let Some(sync_message) = msg.sync_message {
+        handle_sync_message(db, account, sync_message, sender).await;
+    }
+}
+
+async fn handle_typing(account: String, envelope: IncomingMessageV1, sender: Sender<Notification>) {
+    let number = envelope.source.get_number();
+
+    // Our other devices typing
+    if account == number {
+        return;
+    }
+
+    let typing = envelope.typing_message.unwrap();
+    sender.send(
+        Notification::Typing(
+            Typing {
+                account,
+                number,
+                groupid: typing.group_id,
+                started: typing.action.as_deref() == Some("STARTED")
+            }
+        )
+    ).await.expect("Failed to send notification");
+}
+
+// Receipts for messages sent from our other devices land here too, as
+// they're matched on timestamp alone
+async fn handle_receipt(db: Arc<Mutex<SqliteConnection>>, account: String, envelope: IncomingMessageV1, sender: Sender<Notification>) {
+    let member = envelope.source.get_number();
+    let receipt = envelope.receipt_message.unwrap();
+
+    let status = match receipt.type_.as_deref() {
+        Some("DELIVERY") => MessageStatus::Delivered,
+        Some("READ") | Some("VIEWED") => MessageStatus::Read,
+        _ => return
+    };
+
+    let updated = database::store_receipt(
+        &db.lock().unwrap(),
+        &account,
+        receipt.timestamps.as_deref().unwrap_or_default(),
+        &member,
+        status,
+        receipt.when.or(envelope.timestamp).unwrap_or_default()
+    );
+
+    if !updated.is_empty() {
+        sender.send(Notification::Receipt(updated)).await.expect("Failed to send notification");
+    }
+}
+
+async fn handle_data_msg(db: Arc<Mutex<SqliteConnection>>, account: String, envelope: IncomingMessageV1, sender: Sender<Notification>) {
+    // Check that message isn't just a reaction
+    if envelope.data_message.as_ref().unwrap().reaction.is_some() {
+        handle_reaction(
+            db,
+            account,
+            envelope.source.get_number(),
+            envelope.data_message.unwrap(),
+            sender
+        ).await;
+        return;
+    }
+
+    // Should only be called if it's determined to contain data_message
+    let msg = envelope.data_message.unwrap();
+    let timestamp = msg.timestamp.unwrap();
+    let number = envelope.source.unwrap().number;
+
+    // Pictures and files can come without any text
+    let has_attachments = msg.attachments.as_ref().map_or(false, |attachments| !attachments.is_empty());
+    if msg.body.is_none() && !has_attachments {
+        return;
+    }
+
+    let attachments = database::store_attachments(&db.lock().unwrap(), msg.attachments.as_ref());
+    let body = msg.body.unwrap_or_default();
+    let groupid = msg.group_v_2.as_ref().map(|group| {
+        group.id.unwrap_clone()
+    });
+    let quote_timestamp = msg.quote.as_ref().map(|quote| {
+        quote.id.unwrap()
+    });
+    let quote_author = msg.quote.as_ref().map(|quote| {
+        quote.author.get_number()
+    });
+    let (mentions, mentions_start) = database::convert_mentions(&msg.mentions);
+
+    let msg = NewMessage {
+        timestamp,
+        number,
+        from_me: false,
+        is_read: false,
+        attachments,
+        body,
+        groupid,
+        quote_timestamp,
+        quote_author,
+        mentions,
+        mentions_start,
+        status: MessageStatus::Sent as i32,
+        account
+    };
+
+    database::store_message(&db.lock().unwrap(), &msg);
+    sender.send(Notification::NewMessage(msg)).await.expect("Failed to send notification");
+}
+
+async fn handle_sync_message(db: Arc<Mutex<SqliteConnection>>, account: String, msg: JsonSyncMessageV1, sender: Sender<Notification>) {
+    if let Some(fetch_type) = msg.fetch_type {
+        log::debug!("Sync fetch type: {}", fetch_type);
+    }
+
+    if let Some(read_messages) = msg.read_messages {
+        handle_read_sync(db.clone(), account.clone(), read_messages, sender.clone()).await;
+    }
+
+    if let Some(sent) = msg.sent {
+        let msg_packet = sent.message.unwrap();
+
+        // Reaction made on another of our devices
+        if msg_packet.reaction.is_some() {
+            handle_reaction(db, account.clone(), account, msg_packet, sender).await;
+            return;
+        }
+
+        let destination = sent.destination.unwrap();
+        let (mentions, mentions_start) = database::convert_mentions(&msg_packet.mentions);
+        let msg = NewMessage {
+            timestamp: sent.timestamp.unwrap(),
+            number: destination.number,
+            from_me: true,
+            is_read: false,
+            body: msg_packet.body.unwrap_or_default(),
+            attachments: database::store_attachments(&db.lock().unwrap(), msg_packet.attachments.as_ref()),
+            groupid: msg_packet.group_v_2.as_ref().map(|group| {
+                group.id.unwrap_clone()
+            }),
+            quote_timestamp: msg_packet.quote.as_ref().map(|quote| {
+                quote.id.unwrap()
+            }),
+            quote_author: msg_packet.quote.as_ref().map(|quote| {
+                quote.author.get_number()
+            }),
+            mentions,
+            mentions_start,
+            status: MessageStatus::Sent as i32,
+            account
+        };
+
+        database::store_message(&db.lock().unwrap(), &msg);
+    }
+}
+
+// Messages we read on another of our devices
+async fn handle_read_sync(db: Arc<Mutex<SqliteConnection>>, account: String, read_messages: Vec<JsonReadMessage>, sender: Sender<Notification>) {
+    let mut read: HashMap<String, Vec<i64>> = HashMap::new();
+    for read_message in read_messages {
+        if let (Some(number), Some(timestamp)) = (read_message.sender.and_then(|sender| sender.number), read_message.timestamp) {
+            read.entry(number).or_default().push(timestamp);
+        }
+    }
+
+    if read.is_empty() {
+        return;
+    }
+
+    for (number, timestamps) in &read {
+        database::read_msgs(&db.lock().unwrap(), &account, timestamps, number);
+    }
+
+    sender.send(Notification::Read { account, read }).await.expect("Failed to send notification");
+}
+
+async fn handle_reaction(db: Arc<Mutex<SqliteConnection>>, account: String, author: String, msg: JsonDataMessageV1, sender: Sender<Notification>) {
+    let reaction = match msg.reaction {
+        Some(reaction) => reaction,
+        None => return
+    };
+
+    // Drop anything incomplete rather than take the bridge down with it
+    let target_author = reaction.target_author.and_then(|address| address.number);
+    let (emoji, target_timestamp, target_author, timestamp) = match (reaction.emoji, reaction.target_sent_timestamp, target_author, msg.timestamp) {
+        (Some(emoji), Some(target_timestamp), Some(target_author), Some(timestamp)) => (emoji, target_timestamp, target_author, timestamp),
+        _ => {
+            log::warn!("Dropping incomplete reaction from {}", author);
+            return;
+        }
+    };
+
+    let groupid = msg.group_v_2.as_ref().map(|group| {
+        group.id.unwrap_clone()
+    });
+    let remove = reaction.remove.unwrap_or(false);
+
+    let target = database::find_authored_message(
+        &db.lock().unwrap(),
+        target_timestamp,
+        &target_author,
+        &account,
+        &groupid
+    );
+
+    // Reaction to a message from before we were linked
+    let target = match target {
+        Some(target) => target,
+        None => return
+    };
+
+    database::store_reaction(&db.lock().unwrap(), &target, &author, &emoji, timestamp, remove);
+
+    sender.send(
+        Notification::Reaction(
+            Reaction {
+                account,
+                emoji,
+                author,
+                remove,
+                message_timestamp: target.timestamp,
+                message_number: target.number,
+                message_from_me: target.from_me,
+                groupid
+            }
+        )
+    ).await.expect("Failed to send notification");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::channel::bounded;
+    use signald::types::{FinishLinkRequestV1, JsonAddressV1, SendRequestV1};
+
+    use crate::test_support::test_db;
+    use crate::test_support::mock_signald::{self, MockSignald};
+
+    const ACCOUNT: &str = "+15550000000";
+    const CONTACT: &str = "+15551111111";
+
+    struct Bridge {
+        requests: Sender<SignaldInteraction>,
+        notifications: Receiver<Notification>,
+        db: Arc<Mutex<SqliteConnection>>
+    }
+
+    impl Bridge {
+        async fn start(mock: &MockSignald) -> Self {
+            let (requests, request_receiver) = bounded(10);
+            let (notification_sender, notifications) = bounded(10);
+            let db = Arc::new(Mutex::new(test_db()));
+            let config = BridgeConfig {
+                socket_paths: vec![mock.socket_path()],
+                keepalive: Duration::from_millis(200)
+            };
+
+            async_std::task::spawn(listen(config, db.clone(), request_receiver, notification_sender));
+
+            let bridge = Bridge {
+                requests,
+                notifications,
+                db
+            };
+            bridge.wait_until_connected().await;
+
+            bridge
+        }
+
+        async fn wait_until_connected(&self) {
+            loop {
+                if let Notification::Connection(ConnectionState::Connected) = self.next_notification().await {
+                    return;
+                }
+            }
+        }
+
+        async fn next_notification(&self) -> Notification {
+            timeout(Duration::from_secs(5), self.notifications.recv()).await
+                .expect("No notification from bridge")
+                .expect("Notification channel closed")
+        }
+
+        async fn call(&self, key: &'static str, msg: SignaldTypes) -> SignaldResponse {
+            let (request, response_channel) = PendingRequest::new(key, Duration::from_secs(5));
+            self.requests.send(
+                SignaldInteraction {
+                    key,
+                    msg,
+                    timeout: Duration::from_secs(5),
+                    response_channel: Some(response_channel)
+                }
+            ).await.expect("Bridge stopped");
+
+            request.response().await
+        }
+    }
+
+    fn send_request(body: &str) -> SendRequestV1 {
+        SendRequestV1 {
+            username: Some(ACCOUNT.to_owned()),
+            recipient_address: JsonAddressV1::from_number(CONTACT.to_owned()),
+            recipient_group_id: None,
+            message_body: Some(body.to_owned()),
+            attachments: None,
+            quote: None,
+            timestamp: Some(1000),
+            mentions: None
+        }
+    }
+
+    #[test]
+    fn expands_env_vars() {
+        env::set_var("SIGNAL_RS_TEST_DIR", "/run/user/1000");
+
+        assert_eq!(
+            expand_env_vars("$SIGNAL_RS_TEST_DIR/signald/signald.sock").as_deref(),
+            Some("/run/user/1000/signald/signald.sock")
+        );
+        assert_eq!(
+            expand_env_vars("${SIGNAL_RS_TEST_DIR}/signald.sock").as_deref(),
+            Some("/run/user/1000/signald.sock")
+        );
+        assert_eq!(expand_env_vars("$SIGNAL_RS_TEST_UNSET/signald.sock"), None);
+        assert_eq!(expand_env_vars("/var/run/signald/signald.sock").as_deref(), Some("/var/run/signald/signald.sock"));
+    }
+
+    #[async_std::test]
+    async fn answers_requests() {
+        let mock = MockSignald::start().await;
+        mock.respond("list_accounts", mock_signald::account_list(&[ACCOUNT]));
+        let bridge = Bridge::start(&mock).await;
+
+        let response = bridge.call(
+            "list_accounts",
+            SignaldTypes::ListAccountsRequestV1(ListAccountsRequestV1::default())
+        ).await;
+
+        match response {
+            Ok(SignaldTypes::AccountListV1(list)) => {
+                let accounts = list.accounts.unwrap();
+                assert_eq!(accounts[0].account_id.as_deref(), Some(ACCOUNT));
+            },
+            _ => panic!("Expected an account list")
+        }
+    }
+
+    #[async_std::test]
+    async fn answers_requests_while_others_hang() {
+        let mock = MockSignald::start().await;
+        mock.never_respond("finish_link");
+        let bridge = Bridge::start(&mock).await;
+
+        let (hanging, response_channel) = PendingRequest::new("finish_link", Duration::from_secs(5));
+        bridge.requests.send(
+            SignaldInteraction {
+                key: "finish_link",
+                msg: SignaldTypes::FinishLinkRequestV1(FinishLinkRequestV1::default()),
+                timeout: Duration::from_millis(300),
+                response_channel: Some(response_channel)
+            }
+        ).await.expect("Bridge stopped");
+
+        let response = timeout(
+            Duration::from_secs(1),
+            bridge.call("list_accounts", SignaldTypes::ListAccountsRequestV1(ListAccountsRequestV1::default()))
+        ).await.expect("Stuck behind finish_link");
+        assert!(response.is_ok());
+
+        // Given up on by the bridge, not just the caller
+        assert!(matches!(hanging.response().await, Err(Error::Timeout("finish_link"))));
+    }
+
+    #[async_std::test]
+    async fn answers_requests_while_pings_hang() {
+        let mock = MockSignald::start().await;
+        mock.never_respond("list_accounts");
+        let bridge = Bridge::start(&mock).await;
+
+        // Long enough for the keepalive to start pinging
+        mock.wait_for_request("list_accounts").await;
+
+        let send = bridge.call("send", SignaldTypes::SendRequestV1(send_request("hi")));
+        timeout(Duration::from_secs(1), send).await
+            .expect("Request held up behind the ping")
+            .expect("Failed to send");
+    }
+
+    #[async_std::test]
+    async fn reconnects_when_signald_restarts() {
+        let mock = MockSignald::start().await;
+        let bridge = Bridge::start(&mock).await;
+
+        let subscribe = SignaldTypes::SubscribeRequestV1(SubscribeRequestV1 {
+            account: Some(ACCOUNT.to_owned())
+        });
+        bridge.call("subscribe", subscribe).await.expect("Failed to subscribe");
+
+        // Nothing's asked of the bridge, so only the keepalive can notice
+        mock.drop_clients();
+        assert!(matches!(bridge.next_notification().await, Notification::Connection(ConnectionState::Connecting)));
+        bridge.wait_until_connected().await;
+
+        for _ in 0..100 {
+            if mock.requests("subscribe").len() == 2 {
+                break;
+            }
+            async_std::task::sleep(Duration::from_millis(50)).await;
+        }
+        assert_eq!(mock.requests("subscribe").len(), 2);
+
+        mock.push_incoming(mock_signald::text_message(ACCOUNT, CONTACT, 1000, "still here")).await;
+        assert!(matches!(bridge.next_notification().await, Notification::NewMessage(_)));
+    }
+
+    #[async_std::test]
+    async fn reports_signald_errors() {
+        let mock = MockSignald::start().await;
+        mock.respond_error("send", "RateLimitError", "rate limit exceeded");
+        let bridge = Bridge::start(&mock).await;
+
+        let response = bridge.call("send", SignaldTypes::SendRequestV1(send_request("hi"))).await;
+
+        assert!(matches!(response, Err(Error::RateLimited(_))));
+    }
+
+    #[async_std::test]
+    async fn stores_incoming_messages() {
+        let mock = MockSignald::start().await;
+        let bridge = Bridge::start(&mock).await;
+
+        mock.wait_for_client().await;
+        mock.push_incoming(mock_signald::text_message(ACCOUNT, CONTACT, 1000, "hello")).await;
+
+        match bridge.next_notification().await {
+            Notification::NewMessage(msg) => {
+                assert_eq!(msg.body, "hello");
+                assert_eq!(msg.number.as_deref(), Some(CONTACT));
+            },
+            _ => panic!("Expected a new message")
+        }
+
+        let stored = database::get_message(
+            &bridge.db.lock().unwrap(),
+            ACCOUNT,
+            1000,
+            Some(CONTACT.to_owned()),
+            false,
+            None
+        );
+        assert_eq!(stored.body, "hello");
+        assert!(!stored.is_read);
+    }
+
+    #[async_std::test]
+    async fn stores_attachments_without_text() {
+        let mock = MockSignald::start().await;
+        let bridge = Bridge::start(&mock).await;
+
+        mock.wait_for_client().await;
+        mock.push_incoming(mock_signald::attachment_message(ACCOUNT, CONTACT, 1000, "image/jpeg")).await;
+
+        let msg = match bridge.next_notification().await {
+            Notification::NewMessage(msg) => msg,
+            _ => panic!("Expected a new message")
+        };
+        assert_eq!(msg.body, "");
+
+        let id = msg.attachments.as_ref().unwrap().lines().next().unwrap();
+        let attachment = database::get_attachment(&bridge.db.lock().unwrap(), id).unwrap();
+        assert_eq!(attachment.content_type, "image/jpeg");
+    }
+
+    #[async_std::test]
+    async fn forwards_typing_indicators() {
+        let mock = MockSignald::start().await;
+        let bridge = Bridge::start(&mock).await;
+
+        mock.wait_for_client().await;
+        mock.push_incoming(mock_signald::typing_message(ACCOUNT, CONTACT, Some("group-id"), true)).await;
+
+        match bridge.next_notification().await {
+            Notification::Typing(typing) => {
+                assert_eq!(typing.number, CONTACT);
+                assert_eq!(typing.groupid.as_deref(), Some("group-id"));
+                assert!(typing.started);
+            },
+            _ => panic!("Expected a typing indicator")
+        }
+    }
+
+    #[async_std::test]
+    async fn stores_incoming_reactions() {
+        let mock = MockSignald::start().await;
+        let bridge = Bridge::start(&mock).await;
+
+        mock.wait_for_client().await;
+        mock.push_incoming(mock_signald::text_message(ACCOUNT, CONTACT, 1000, "hello")).await;
+        bridge.next_notification().await;
+        mock.push_incoming(mock_signald::reaction_message(ACCOUNT, CONTACT, 2000, CONTACT, 1000, "👍", false)).await;
+
+        match bridge.next_notification().await {
+            Notification::Reaction(reaction) => {
+                assert_eq!(reaction.emoji, "👍");
+                assert_eq!(reaction.author, CONTACT);
+                assert_eq!(reaction.message_timestamp, 1000);
+                assert!(!reaction.message_from_me);
+            },
+            _ => panic!("Expected a reaction")
+        }
+
+        let db = bridge.db.lock().unwrap();
+        let target = database::find_authored_message(&db, 1000, CONTACT, ACCOUNT, &None).unwrap();
+        let reactions = database::get_reactions(&db, &target);
+        assert_eq!(reactions.len(), 1);
+        assert_eq!(reactions[0].emoji, "👍");
+        assert_eq!(reactions[0].author, CONTACT);
+        assert_eq!(reactions[0].timestamp, 2000);
+    }
+
+    #[async_std::test]
+    async fn drops_incomplete_reactions() {
+        let mock = MockSignald::start().await;
+        let bridge = Bridge::start(&mock).await;
+
+        mock.wait_for_client().await;
+        mock.push_incoming(mock_signald::text_message(ACCOUNT, CONTACT, 1000, "hello")).await;
+        bridge.next_notification().await;
+
+        let mut reaction = mock_signald::reaction_message(ACCOUNT, CONTACT, 2000, CONTACT, 1000, "👍", false);
+        reaction["data_message"]["reaction"]["emoji"] = serde_json::Value::Null;
+        mock.push_incoming(reaction).await;
+
+        // Still receiving
+        mock.push_incoming(mock_signald::text_message(ACCOUNT, CONTACT, 3000, "still here")).await;
+        match bridge.next_notification().await {
+            Notification::NewMessage(msg) => assert_eq!(msg.body, "still here"),
+            _ => panic!("Expected a new message")
+        }
+    }
+
+    #[async_std::test]
+    async fn stores_receipts() {
+        let mock = MockSignald::start().await;
+        let bridge = Bridge::start(&mock).await;
+
+        database::store_message(&bridge.db.lock().unwrap(), &NewMessage {
+            timestamp: 1000,
+            number: Some(CONTACT.to_owned()),
+            from_me: true,
+            is_read: false,
+            attachments: None,
+            body: "hello".to_owned(),
+            groupid: None,
+            quote_timestamp: None,
+            quote_author: None,
+            mentions: None,
+            mentions_start: None,
+            status: MessageStatus::Sent as i32,
+            account: ACCOUNT.to_owned()
+        });
+
+        mock.wait_for_client().await;
+        mock.push_incoming(mock_signald::receipt_message(ACCOUNT, CONTACT, "READ", &[1000], 2000)).await;
+
+        match bridge.next_notification().await {
+            Notification::Receipt(messages) => {
+                assert_eq!(messages.len(), 1);
+                assert_eq!(messages[0].timestamp, 1000);
+                assert_eq!(messages[0].status(), MessageStatus::Read);
+            },
+            _ => panic!("Expected a receipt")
+        }
+    }
+}
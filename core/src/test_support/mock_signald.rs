@@ -0,0 +1,408 @@
+//! A stand-in for signald which listens on a Unix socket in a temporary
+//! directory and speaks just enough of its JSON protocol for tests. Responses
+//! are scripted per request type and incoming envelopes can be pushed to
+//! every connected client.
+
+use async_std::io::BufReader;
+use async_std::os::unix::net::{UnixListener, UnixStream};
+use async_std::prelude::*;
+use async_std::task;
+use serde_json::{json, Value};
+use tempfile::TempDir;
+
+use std::collections::HashMap;
+use std::net::Shutdown;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+enum Scripted {
+    Data(Value),
+    Error {
+        error_type: String,
+        message: String
+    },
+    Silence
+}
+
+#[derive(Clone, Default)]
+struct State {
+    responses: Arc<Mutex<HashMap<String, Scripted>>>,
+    requests: Arc<Mutex<Vec<Value>>>,
+    clients: Arc<Mutex<Vec<UnixStream>>>
+}
+
+pub struct MockSignald {
+    // Removes the socket when the mock is dropped
+    _dir: TempDir,
+    path: String,
+    state: State
+}
+
+impl MockSignald {
+    pub async fn start() -> Self {
+        let dir = tempfile::tempdir().expect("Failed to create socket directory");
+        let path = dir.path().join("signald.sock").to_str().unwrap().to_owned();
+        let listener = UnixListener::bind(&path).await.expect("Failed to bind mock socket");
+        let state = State::default();
+
+        task::spawn(accept(listener, state.clone()));
+
+        MockSignald {
+            _dir: dir,
+            path,
+            state
+        }
+    }
+
+    pub fn socket_path(&self) -> String {
+        self.path.clone()
+    }
+
+    /// Answer every request of type `key` with `data`. Unscripted requests
+    /// get an empty object.
+    pub fn respond(&self, key: &str, data: Value) {
+        self.state.responses.lock().unwrap().insert(key.to_owned(), Scripted::Data(data));
+    }
+
+    /// Answer every request of type `key` with a signald error
+    pub fn respond_error(&self, key: &str, error_type: &str, message: &str) {
+        self.state.responses.lock().unwrap().insert(
+            key.to_owned(),
+            Scripted::Error {
+                error_type: error_type.to_owned(),
+                message: message.to_owned()
+            }
+        );
+    }
+
+    /// Never answer requests of type `key`, like a signald that hangs
+    pub fn never_respond(&self, key: &str) {
+        self.state.responses.lock().unwrap().insert(key.to_owned(), Scripted::Silence);
+    }
+
+    /// Deliver an `IncomingMessageV1` to every connected client
+    pub async fn push_incoming(&self, envelope: Value) {
+        let line = json!({
+            "type": "IncomingMessage",
+            "version": "v1",
+            "data": envelope
+        });
+
+        let clients: Vec<UnixStream> = self.state.clients.lock().unwrap().clone();
+        for mut client in clients {
+            write_line(&mut client, &line).await;
+        }
+    }
+
+    /// Hang up on every client, like signald restarting
+    pub fn drop_clients(&self) {
+        for client in self.state.clients.lock().unwrap().drain(..) {
+            client.shutdown(Shutdown::Both).ok();
+        }
+    }
+
+    /// All requests received so far of type `key`
+    pub fn requests(&self, key: &str) -> Vec<Value> {
+        self.state.requests.lock().unwrap().iter().filter(|request| {
+            request["type"] == key
+        }).cloned().collect()
+    }
+
+    /// Wait up to a few seconds for a request of type `key`
+    pub async fn wait_for_request(&self, key: &str) -> Value {
+        for _ in 0..100 {
+            if let Some(request) = self.requests(key).pop() {
+                return request;
+            }
+            task::sleep(Duration::from_millis(50)).await;
+        }
+
+        panic!("signald never received {}", key);
+    }
+
+    /// Wait until at least one client is connected, so pushed envelopes
+    /// aren't lost
+    pub async fn wait_for_client(&self) {
+        for _ in 0..100 {
+            if !self.state.clients.lock().unwrap().is_empty() {
+                return;
+            }
+            task::sleep(Duration::from_millis(50)).await;
+        }
+
+        panic!("Nothing connected to mock signald");
+    }
+}
+
+async fn accept(listener: UnixListener, state: State) {
+    let mut incoming = listener.incoming();
+    while let Some(stream) = incoming.next().await {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue
+        };
+
+        // signald greets every client with its version
+        write_line(&mut stream, &json!({
+            "type": "version",
+            "version": "v1",
+            "data": {
+                "name": "signald",
+                "version": "mock",
+                "branch": "mock",
+                "commit": "mock"
+            }
+        })).await;
+
+        state.clients.lock().unwrap().push(stream.clone());
+        task::spawn(serve(stream, state.clone()));
+    }
+}
+
+async fn serve(stream: UnixStream, state: State) {
+    let mut writer = stream.clone();
+    let mut lines = BufReader::new(stream).lines();
+
+    while let Some(Ok(line)) = lines.next().await {
+        let request: Value = match serde_json::from_str(&line) {
+            Ok(request) => request,
+            Err(_) => continue
+        };
+
+        let key = request["type"].as_str().unwrap_or_default().to_owned();
+        let id = request["id"].clone();
+        state.requests.lock().unwrap().push(request);
+
+        let response = match state.responses.lock().unwrap().get(&key) {
+            Some(Scripted::Data(data)) => json!({
+                "type": key,
+                "id": id,
+                "version": "v1",
+                "data": data
+            }),
+            Some(Scripted::Error { error_type, message }) => json!({
+                "type": key,
+                "id": id,
+                "version": "v1",
+                "error_type": error_type,
+                "error": {
+                    "message": message
+                }
+            }),
+            Some(Scripted::Silence) => continue,
+            None => json!({
+                "type": key,
+                "id": id,
+                "version": "v1",
+                "data": {}
+            })
+        };
+
+        write_line(&mut writer, &response).await;
+    }
+}
+
+async fn write_line(stream: &mut UnixStream, value: &Value) {
+    let mut line = value.to_string();
+    line.push('\n');
+    // A client going away isn't a test failure in itself
+    stream.write_all(line.as_bytes()).await.ok();
+}
+
+pub fn address(number: &str) -> Value {
+    json!({
+        "number": number,
+        "uuid": "00000000-0000-0000-0000-000000000000"
+    })
+}
+
+/// An incoming 1:1 text message envelope
+pub fn text_message(account: &str, source: &str, timestamp: i64, body: &str) -> Value {
+    json!({
+        "account": account,
+        "source": address(source),
+        "source_device": 1,
+        "type": "CIPHERTEXT",
+        "timestamp": timestamp,
+        "data_message": {
+            "timestamp": timestamp,
+            "body": body
+        }
+    })
+}
+
+/// An incoming message carrying one attachment and no text
+pub fn attachment_message(account: &str, source: &str, timestamp: i64, content_type: &str) -> Value {
+    json!({
+        "account": account,
+        "source": address(source),
+        "source_device": 1,
+        "type": "CIPHERTEXT",
+        "timestamp": timestamp,
+        "data_message": {
+            "timestamp": timestamp,
+            "attachments": [{
+                "content_type": content_type,
+                "id": "1234",
+                "size": 16,
+                "stored_filename": "/tmp/signald/attachments/1234"
+            }]
+        }
+    })
+}
+
+/// An incoming reaction to the message `target_author` sent at
+/// `target_timestamp`
+pub fn reaction_message(account: &str, source: &str, timestamp: i64, target_author: &str, target_timestamp: i64, emoji: &str, remove: bool) -> Value {
+    json!({
+        "account": account,
+        "source": address(source),
+        "source_device": 1,
+        "type": "CIPHERTEXT",
+        "timestamp": timestamp,
+        "data_message": {
+            "timestamp": timestamp,
+            "reaction": {
+                "emoji": emoji,
+                "remove": remove,
+                "target_author": address(target_author),
+                "target_sent_timestamp": target_timestamp
+            }
+        }
+    })
+}
+
+/// Someone starting or stopping typing, in a group if `group_id` is set
+pub fn typing_message(account: &str, source: &str, group_id: Option<&str>, started: bool) -> Value {
+    json!({
+        "account": account,
+        "source": address(source),
+        "source_device": 1,
+        "type": "CIPHERTEXT",
+        "timestamp": 1,
+        "typing_message": {
+            "action": if started { "STARTED" } else { "STOPPED" },
+            "timestamp": 1,
+            "group_id": group_id
+        }
+    })
+}
+
+pub fn receipt_message(account: &str, source: &str, type_: &str, timestamps: &[i64], when: i64) -> Value {
+    json!({
+        "account": account,
+        "source": address(source),
+        "source_device": 1,
+        "type": "RECEIPT",
+        "timestamp": when,
+        "receipt_message": {
+            "type": type_,
+            "timestamps": timestamps,
+            "when": when
+        }
+    })
+}
+
+/// Our other device read messages, given as (sender, timestamp)
+pub fn read_sync_message(account: &str, read: &[(&str, i64)]) -> Value {
+    let read_messages: Vec<Value> = read.iter().map(|(sender, timestamp)| {
+        json!({
+            "sender": address(sender),
+            "timestamp": timestamp
+        })
+    }).collect();
+
+    json!({
+        "account": account,
+        "source": address(account),
+        "source_device": 2,
+        "type": "CIPHERTEXT",
+        "timestamp": 3000,
+        "sync_message": {
+            "read_messages": read_messages
+        }
+    })
+}
+
+pub fn account_list(accounts: &[&str]) -> Value {
+    let accounts: Vec<Value> = accounts.iter().map(|account| {
+        json!({
+            "account_id": account,
+            "device_id": 1,
+            "address": address(account)
+        })
+    }).collect();
+
+    json!({
+        "accounts": accounts
+    })
+}
+
+pub fn profile_list(contacts: &[(&str, &str)]) -> Value {
+    let profiles: Vec<Value> = contacts.iter().map(|(number, name)| {
+        json!({
+            "name": name,
+            "address": address(number)
+        })
+    }).collect();
+
+    json!({
+        "profiles": profiles
+    })
+}
+
+pub fn group_list(groups: &[(&str, &str)]) -> Value {
+    let groups: Vec<Value> = groups.iter().map(|(id, title)| {
+        json!({
+            "id": id,
+            "title": title,
+            "revision": 1
+        })
+    }).collect();
+
+    json!({
+        "groups": groups,
+        "legacyGroups": []
+    })
+}
+
+/// Devices of an account as (id, name), linked and last seen at 1000
+pub fn linked_devices(devices: &[(i64, &str)]) -> Value {
+    let devices: Vec<Value> = devices.iter().map(|(id, name)| {
+        json!({
+            "id": id,
+            "name": name,
+            "created": 1000,
+            "last_seen": 1000
+        })
+    }).collect();
+
+    json!({
+        "devices": devices
+    })
+}
+
+/// A send response with one result per `(number, delivered)` recipient
+pub fn send_results(recipients: &[(&str, bool)]) -> Value {
+    let results: Vec<Value> = recipients.iter().map(|(number, delivered)| {
+        if *delivered {
+            json!({
+                "address": address(number),
+                "success": {
+                    "unidentified": false,
+                    "needs_sync": true
+                }
+            })
+        } else {
+            json!({
+                "address": address(number),
+                "network_failure": true
+            })
+        }
+    }).collect();
+
+    json!({
+        "results": results,
+        "timestamp": 1
+    })
+}
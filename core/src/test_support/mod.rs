@@ -0,0 +1,38 @@
+//! Helpers for exercising the bridge and database without a real signald
+//! or chat database
+
+pub mod mock_signald;
+
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+
+// Keep in sync with the migrations directory
+pub const MIGRATIONS: [&str; 11] = [
+    include_str!("../../migrations/2021-08-29-154046_create_messages/up.sql"),
+    include_str!("../../migrations/2021-08-31-020348_create_attachments/up.sql"),
+    include_str!("../../migrations/2021-09-12-173512_create_reactions/up.sql"),
+    include_str!("../../migrations/2021-09-19-101500_add_attachment_dimensions/up.sql"),
+    include_str!("../../migrations/2021-09-26-120000_create_conversation_settings/up.sql"),
+    include_str!("../../migrations/2021-10-03-090000_create_settings/up.sql"),
+    include_str!("../../migrations/2021-10-10-090000_create_receipts/up.sql"),
+    include_str!("../../migrations/2021-10-17-090000_create_outbox/up.sql"),
+    include_str!("../../migrations/2021-10-24-090000_partition_by_account/up.sql"),
+    include_str!("../../migrations/2021-10-31-090000_mute_per_account/up.sql"),
+    include_str!("../../migrations/2021-11-07-090000_reactions_by_conversation/up.sql")
+];
+
+/// Index of the migration that moved reactions out of the messages table
+pub const REACTIONS_MIGRATION: usize = 2;
+
+/// An in-memory database with all migrations applied
+pub fn test_db() -> SqliteConnection {
+    let db = SqliteConnection::establish(":memory:")
+        .expect("Failed to open in-memory database");
+
+    for migration in MIGRATIONS.iter() {
+        db.batch_execute(migration).expect("Failed to run migration");
+    }
+
+    db
+}
@@ -0,0 +1,134 @@
+use gtk::prelude::*;
+use gtk::{Box as Box_, Button, CheckButton, Inhibit, MenuButton, Orientation, Popover};
+use gtk::gio;
+use gtk::glib::{self, clone, MainContext};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use signal_rs_core::settings::{RUN_IN_BACKGROUND, SEND_TYPING, START_ON_LOGIN};
+
+use crate::app::App;
+
+impl App {
+    /// Closing the window only hides it when running in the background, so
+    /// the bridge keeps receiving and the window reopens from where it was.
+    /// Started with `--gapplication-service` (e.g. on login), the window
+    /// isn't shown until the app is activated.
+    pub fn setup_background(self: Rc<App>) {
+        self.window.connect_close_request(clone!(@strong self as app => move |window| {
+            if app.client.flag(RUN_IN_BACKGROUND, true) {
+                window.hide();
+            } else {
+                app.application.quit();
+            }
+
+            Inhibit(true)
+        }));
+
+        let quit = gio::SimpleAction::new("quit", None);
+        quit.connect_activate(clone!(@strong self as app => move |_, _| {
+            app.application.quit();
+        }));
+        self.application.add_action(&quit);
+        self.application.set_accels_for_action("app.quit", &["<Primary>q"]);
+    }
+
+    /// Background, login and privacy preferences, linked devices, and a way
+    /// to really quit
+    pub fn app_menu(self: Rc<App>) -> MenuButton {
+        let background = CheckButton::with_label("Keep running when closed");
+        background.set_active(self.client.flag(RUN_IN_BACKGROUND, true));
+
+        let login = CheckButton::with_label("Start on login");
+        login.set_active(self.client.flag(START_ON_LOGIN, false));
+
+        let typing = CheckButton::with_label("Send typing indicators");
+        typing.set_active(self.client.flag(SEND_TYPING, true));
+
+        background.connect_toggled(clone!(@strong self as app => move |background| {
+            app.client.set_flag(RUN_IN_BACKGROUND, background.is_active());
+        }));
+
+        login.connect_toggled(clone!(@strong self as app => move |login| {
+            let enabled = login.is_active();
+            match set_start_on_login(&app.application, enabled) {
+                Ok(()) => app.client.set_flag(START_ON_LOGIN, enabled),
+                Err(e) => app.flash_status(&format!("Couldn't change autostart: {}", e))
+            }
+        }));
+
+        typing.connect_toggled(clone!(@strong self as app => move |typing| {
+            app.client.set_flag(SEND_TYPING, typing.is_active());
+        }));
+
+        let devices = Button::builder()
+            .label("Linked devices")
+            .build();
+
+        let quit = Button::builder()
+            .label("Quit")
+            .action_name("app.quit")
+            .build();
+
+        let vbox = Box_::new(Orientation::Vertical, 5);
+        vbox.append(&background);
+        vbox.append(&login);
+        vbox.append(&typing);
+        vbox.append(&devices);
+        vbox.append(&quit);
+
+        let popover = Popover::builder()
+            .child(&vbox)
+            .build();
+
+        devices.connect_clicked(clone!(@strong self as app, @weak popover => move |_| {
+            popover.popdown();
+            MainContext::default().spawn_local(clone!(@strong app => async move {
+                app.show_linked_devices().await;
+            }));
+        }));
+
+        MenuButton::builder()
+            .icon_name("open-menu")
+            .popover(&popover)
+            .build()
+    }
+}
+
+fn autostart_file(application: &gtk::Application) -> PathBuf {
+    let id = application.application_id()
+        .map(|id| id.to_string())
+        .unwrap_or_else(|| "signal-rs".to_owned());
+
+    glib::user_config_dir().join("autostart").join(format!("{}.desktop", id))
+}
+
+/// Add or remove an XDG autostart entry launching us in the background
+fn set_start_on_login(application: &gtk::Application, enabled: bool) -> io::Result<()> {
+    let file = autostart_file(application);
+
+    if !enabled {
+        return match fs::remove_file(&file) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(())
+        };
+    }
+
+    let exe = std::env::current_exe()?;
+    let entry = format!(
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name=Signal\n\
+         Exec=\"{}\" --gapplication-service\n\
+         X-GNOME-Autostart-enabled=true\n\
+         NoDisplay=true\n",
+        exe.display()
+    );
+
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&file, entry)
+}
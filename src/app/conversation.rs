@@ -1,126 +1,28 @@
 use gtk::prelude::*;
-use gtk::{Align, Button, Box as Box_, HeaderBar, Orientation, Label, ListView, 
+use gtk::{Align, Button, Box as Box_, CheckButton, Entry, HeaderBar, MenuButton, Orientation, Label, ListView, Popover,
           PolicyType, NoSelection, ScrolledWindow, SignalListItemFactory};
 use gtk::gio;
 use gtk::glib::{self, clone, MainContext};
 use std::rc::Rc;
-use std::cell::{Cell, RefCell};
-use diesel::sqlite::SqliteConnection;
-use std::collections::HashMap;
+use std::cell::Cell;
 
-use signald::types::{ProfileV1, JsonAddressV1, JsonGroupV2InfoV1, MarkReadRequestV1,
-                     SignaldTypes};
+use signal_rs_core::conversation::Conversation;
+use signal_rs_core::database;
+use signal_rs_core::models::{Message, NewMessage};
 
 use crate::app::App;
-use crate::database;
 use crate::app::message::MessageObject;
-use crate::signal_type_utils::*;
-use crate::models::NewMessage;
-
-pub enum ConversationType {
-    Individual(ProfileV1),
-    Group(JsonGroupV2InfoV1)
-}
-
-pub struct Conversation {
-    pub conversation_type: ConversationType,
-    pub name: String,
-    number: Option<String>,
-    groupid: Option<String>,
-    pub model: RefCell<Option<gio::ListStore>>,
-    pub typing: RefCell<bool>,
-    pub last_message_time: RefCell<i64>,
-    pub is_active: RefCell<bool>,
-    pub new_msgs: RefCell<usize>,
-    pub unread: RefCell<HashMap<String, Vec<i64>>>
-}
-
-impl Conversation {
-    pub fn new_individual(profile: ProfileV1, db: &SqliteConnection) -> Option<Self> {
-        // Ask for profile from signal if known profile is incomplete
-        let name = profile.get_name();
-        let number = profile.address.get_number();
-        let (new_msgs, unread) = database::get_unread(db, Some(&number), None);
-
-        if name.is_empty() {
-            None
-        } else {
-            Some(Conversation {
-                conversation_type: ConversationType::Individual(profile),
-                name,
-                number: Some(number),
-                groupid: None,
-                model: RefCell::new(None),
-                typing: RefCell::new(false),
-                last_message_time: RefCell::new(i64::MIN),
-                is_active: RefCell::new(false),
-                new_msgs: RefCell::new(new_msgs),
-                unread: RefCell::new(unread)
-            })
-        }
-    }
-
-    pub fn new_group(group: JsonGroupV2InfoV1, db: &SqliteConnection) -> Option<Self> {
-        let name = group.title.unwrap_clone();
-        let groupid = group.id.unwrap_clone();
-        let (new_msgs, unread) = database::get_unread(db, None, Some(&groupid));
-
-        if name.is_empty() {
-            None
-        } else {
-            Some(Conversation {
-                conversation_type: ConversationType::Group(group),
-                name,
-                number: None,
-                groupid: Some(groupid),
-                model: RefCell::new(None),
-                typing: RefCell::new(false),
-                last_message_time: RefCell::new(i64::MIN),
-                is_active: RefCell::new(false),
-                new_msgs: RefCell::new(new_msgs),
-                unread: RefCell::new(unread)
-            })
-        }
-    }
-
-    pub fn set_last_message(&self, db: &SqliteConnection) {
-        let msg = database::get_most_recent_message(
-            db,
-            &self.number,
-            &self.groupid
-        );
-        if let Some(msg) = msg {
-            self.last_message_time.replace(msg.timestamp);
-        }
-    }
-
-    fn get_name(&self) -> &str {
-        match &self.conversation_type {
-            ConversationType::Individual(profile) => {
-                profile.name.as_ref().unwrap().as_str()
-            },
-            ConversationType::Group(group) => {
-                group.title.as_ref().unwrap().as_str()
-            }
-        }
-    }
-
-    pub fn notify_msg(&self, msg: NewMessage) {
-        if let Some(model) = &*self.model.borrow() {
-            model.append(&MessageObject::new_sent(&msg));
-        }
-
-        if !(*self.is_active.borrow()) {
-            self.new_msgs.replace_with(|&mut num_msgs| num_msgs + 1);
-            let unread = &mut *self.unread.borrow_mut();
-            let number = msg.number.unwrap();
-            if let Some(timestamps) = unread.get_mut(&number) {
-                timestamps.push(msg.timestamp);
-            } else {
-                unread.insert(number, vec![msg.timestamp]);
-            }
-        }
-    }
+use crate::app::message_input::ReplyPreview;
+
+/// The conversation currently on screen and the list model backing it
+pub struct ActiveConversation {
+    pub conversation: Rc<Conversation>,
+    pub model: gio::ListStore,
+    pub list_view: ListView,
+    pub reply: Rc<ReplyPreview>,
+    pub entry: Entry,
+    /// "X is typing…" under the conversation's name
+    pub typing: Label
 }
 
 impl App {
@@ -133,45 +35,152 @@ impl App {
         conversation.is_active.replace(true);
         let vbox = Box_::new(Orientation::Vertical, 5);
 
-        let msg_box = self.clone().message_input_ui(conversation.clone());
+        let reply = ReplyPreview::new();
+        let entry = Entry::builder()
+            .hexpand(true)
+            .show_emoji_icon(true)
+            .build();
+        let msg_box = self.clone().message_input_ui(conversation.clone(), reply.clone(), entry.clone());
+
+        let typing = Label::builder()
+            .css_classes(vec!["typing".to_owned()])
+            .halign(Align::Start)
+            .visible(false)
+            .build();
 
         let header = HeaderBar::builder()
-            .title_widget(&self.clone().get_header_widget(conversation.clone()))
+            .title_widget(&self.clone().get_header_widget(conversation.clone(), &typing))
             .show_title_buttons(true)
             .decoration_layout("icon,menu:close")
             .build();
 
+        let (messages, model, list_view) = self.clone().get_messages(conversation.clone());
+
         vbox.append(&header);
-        vbox.append(&self.clone().get_messages(conversation.clone()));
+        vbox.append(&messages);
         vbox.append(&msg_box);
 
+        self.active.replace(Some(ActiveConversation {
+            conversation,
+            model,
+            list_view,
+            reply,
+            entry,
+            typing
+        }));
+        self.refresh_typing();
+
         self.clone().update_ui(&vbox, "conversation");
     }
 
-    async fn read_messages(self: Rc<App>, conversation: Rc<Conversation>) {
-        conversation.new_msgs.replace(0);
-        for (number, timestamps) in (*conversation.unread.borrow_mut()).drain() {
-            database::read_msgs(&self.db.lock().unwrap(), &timestamps, &number);
-            self.clone().dispatch(
-                "mark_read",
-                SignaldTypes::MarkReadRequestV1(
-                    MarkReadRequestV1 {
-                        account: Some(self.account.borrow().clone()),
-                        timestamps: Some(timestamps),
-                        to: JsonAddressV1::from_number(number),
-                        when: Some(chrono::offset::Local::now().timestamp_millis())
-                    }
-                )
-            ).await;
+    /// Update who's typing in the open conversation, e.g. after a typing
+    /// indicator arrived or expired
+    pub fn refresh_typing(&self) {
+        if let Some(active) = &*self.active.borrow() {
+            match self.typing_text(&active.conversation) {
+                Some(text) => {
+                    active.typing.set_label(&text);
+                    active.typing.show();
+                },
+                None => active.typing.hide()
+            }
+        }
+    }
+
+    /// "X is typing…", naming everyone typing in groups
+    pub fn typing_text(&self, conversation: &Conversation) -> Option<String> {
+        let mut names: Vec<String> = conversation.typists().iter().map(|number| {
+            self.client.get_name(number).unwrap_or_else(|| number.clone())
+        }).collect();
+
+        match names.len() {
+            0 => None,
+            1 => Some(format!("{} is typing…", names[0])),
+            _ => {
+                let last = names.pop().unwrap();
+                Some(format!("{} and {} are typing…", names.join(", "), last))
+            }
+        }
+    }
+
+    pub async fn read_messages(self: Rc<App>, conversation: Rc<Conversation>) {
+        self.withdraw_notification(&conversation);
+
+        if let Err(e) = self.client.mark_read(&conversation).await {
+            self.show_error(&e);
+        }
+    }
+
+    /// Redraw a message in the open conversation, e.g. after a reaction
+    pub fn refresh_message(&self, timestamp: i64, number: &Option<String>, from_me: bool, groupid: &Option<String>) {
+        if let Some(active) = &*self.active.borrow() {
+            if !active.conversation.contains(number, groupid) {
+                return;
+            }
+
+            for position in 0..active.model.n_items() {
+                let item = active.model.item(position)
+                    .expect("The item has to exist.")
+                    .downcast::<MessageObject>()
+                    .expect("The item has to be a MessageObject");
+
+                if item.is_message(timestamp, from_me) {
+                    // Reinserting makes the list view bind the row again
+                    active.model.remove(position);
+                    active.model.insert(position, &item);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Start replying to `msg` in the open conversation
+    pub fn reply_to(&self, msg: Message) {
+        if let Some(active) = &*self.active.borrow() {
+            let author = if msg.from_me {
+                "You".to_owned()
+            } else {
+                msg.number.as_ref()
+                    .and_then(|number| self.client.get_name(number))
+                    .unwrap_or_default()
+            };
+
+            active.reply.show(&author, msg);
         }
     }
 
-    fn get_messages(self: Rc<App>, conversation: Rc<Conversation>) -> ScrolledWindow {
+    /// Scroll the open conversation to a message, e.g. when a quote is tapped
+    pub fn scroll_to_message(&self, timestamp: i64, from_me: bool) {
+        if let Some(active) = &*self.active.borrow() {
+            for position in 0..active.model.n_items() {
+                let item = active.model.item(position)
+                    .expect("The item has to exist.")
+                    .downcast::<MessageObject>()
+                    .expect("The item has to be a MessageObject");
+
+                if item.is_message(timestamp, from_me) {
+                    active.list_view.activate_action("list.scroll-to-item", Some(&position.to_variant()));
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Add a message to the open conversation if it belongs there
+    pub fn append_to_active(&self, msg: &NewMessage) {
+        if let Some(active) = &*self.active.borrow() {
+            if active.conversation.account == msg.account && active.conversation.contains(&msg.number, &msg.groupid) {
+                active.model.append(&MessageObject::new_sent(msg));
+            }
+        }
+    }
+
+    fn get_messages(self: Rc<App>, conversation: Rc<Conversation>) -> (ScrolledWindow, gio::ListStore, ListView) {
         let model = gio::ListStore::new(MessageObject::static_type());
-        let messages = database::query_conversation(&self.db.lock().unwrap(), &conversation.conversation_type);
+        let messages = database::query_conversation(&self.client.db(), &conversation.account, &conversation.conversation_type);
 
         for message in messages {
-            if !message.body.is_empty() {
+            if !message.body.is_empty() || message.attachments.is_some() {
                 let msg = MessageObject::new(message);
 
                 model.append(&msg);
@@ -234,12 +243,10 @@ impl App {
             }
         });
 
-        conversation.model.replace(Some(model));
-
-        window
+        (window, model, list_view)
     }
 
-    fn get_header_widget(self: Rc<App>, conversation: Rc<Conversation>) -> Box_ {
+    fn get_header_widget(self: Rc<App>, conversation: Rc<Conversation>, typing: &Label) -> Box_ {
         let hbox = Box_::new(Orientation::Horizontal, 3);
         let back_button = Button::builder()
             .icon_name("go-previous")
@@ -247,14 +254,20 @@ impl App {
 
         back_button.connect_clicked(clone!(@strong self as app, @strong conversation => move |_| {
             conversation.is_active.replace(false);
+            app.active.replace(None);
             app.update_ui(&app.clone().main_view_ui(), "main_view");
         }));
 
         let name = Label::builder()
             .label(conversation.get_name())
-            .halign(Align::Center)
+            .halign(Align::Start)
             .build();
 
+        let title = Box_::new(Orientation::Vertical, 0);
+        title.set_valign(Align::Center);
+        title.append(&name);
+        title.append(typing);
+
         let video_button = Button::builder()
             .halign(Align::End)
             .icon_name("camera-video")
@@ -265,15 +278,33 @@ impl App {
             .icon_name("phone")
             .build();
 
-        let menu_button = Button::builder()
+        let media_button = Button::builder()
+            .halign(Align::End)
+            .icon_name("image-x-generic")
+            .tooltip_text("Shared media")
+            .build();
+
+        media_button.connect_clicked(clone!(@strong self as app, @strong conversation => move |_| {
+            app.clone().open_gallery(&conversation, None);
+        }));
+
+        let mute = CheckButton::with_label("Mute notifications");
+        mute.set_active(*conversation.muted.borrow());
+        mute.connect_toggled(clone!(@strong self as app, @strong conversation => move |mute| {
+            app.client.set_muted(&conversation, mute.is_active());
+        }));
+
+        let menu_button = MenuButton::builder()
             .halign(Align::End)
             .icon_name("open-menu")
+            .popover(&Popover::builder().child(&mute).build())
             .build();
 
         hbox.append(&back_button);
-        hbox.append(&name);
+        hbox.append(&title);
         hbox.append(&video_button);
         hbox.append(&call_button);
+        hbox.append(&media_button);
         hbox.append(&menu_button);
         hbox.set_halign(Align::Start);
         hbox.set_hexpand(true);
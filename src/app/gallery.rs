@@ -0,0 +1,286 @@
+use gtk::prelude::*;
+use gtk::{Box as Box_, Button, EventControllerScroll, EventControllerScrollFlags, FileChooserAction,
+          FileChooserNative, FlowBox, GestureZoom, HeaderBar, Image, Inhibit, Label, Orientation, Picture,
+          ResponseType, ScrolledWindow, SelectionMode, Stack, Video, Widget, Window};
+use gtk::gdk::ModifierType;
+use gtk::gio;
+use gtk::glib::{self, clone};
+use std::cell::{Cell, RefCell};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use signal_rs_core::conversation::Conversation;
+use signal_rs_core::database;
+
+use crate::app::App;
+
+const MIN_ZOOM: f64 = 1.0;
+const MAX_ZOOM: f64 = 5.0;
+const THUMBNAIL_SIZE: i32 = 120;
+
+/// A fullscreen window over every image and video shared in a
+/// conversation, with a grid of them all and a page per item to swipe
+/// between
+struct Gallery {
+    window: Window,
+    stack: Stack,
+    carousel: adw::Carousel,
+    pages: Vec<(PathBuf, Widget)>,
+    // Native dialogs aren't kept alive by GTK while they're open
+    chooser: RefCell<Option<FileChooserNative>>
+}
+
+impl App {
+    /// Open the gallery of the conversation on screen at `attachment_id`
+    pub fn show_media(self: Rc<App>, attachment_id: &str) {
+        let conversation = match &*self.active.borrow() {
+            Some(active) => active.conversation.clone(),
+            None => return
+        };
+
+        self.open_gallery(&conversation, Some(attachment_id));
+    }
+
+    /// Open the gallery at `attachment_id`, or on the grid if `None`
+    pub fn open_gallery(self: Rc<App>, conversation: &Conversation, attachment_id: Option<&str>) {
+        let media: Vec<(String, String, PathBuf)> = database::get_conversation_media(&self.client.db(), &conversation.account, &conversation.conversation_type)
+            .drain(..)
+            .filter_map(|attachment| {
+                // Skip anything that's missing rather than paging through
+                // placeholders
+                self.client.attachment_path(&attachment).map(|path| {
+                    (attachment.id, attachment.content_type, path)
+                })
+            })
+            .collect();
+
+        let carousel = adw::Carousel::builder()
+            .hexpand(true)
+            .vexpand(true)
+            .build();
+
+        let grid = FlowBox::builder()
+            .selection_mode(SelectionMode::None)
+            .homogeneous(true)
+            .valign(gtk::Align::Start)
+            .build();
+
+        let mut pages = Vec::with_capacity(media.len());
+        let mut start = None;
+        for (index, (id, content_type, path)) in media.iter().enumerate() {
+            let page: Widget = if content_type.starts_with("video/") {
+                Video::builder()
+                    .file(&gio::File::for_path(path))
+                    .hexpand(true)
+                    .vexpand(true)
+                    .build()
+                    .upcast()
+            } else {
+                zoomable_picture(path, &carousel).upcast()
+            };
+
+            carousel.append(&page);
+            grid.insert(&thumbnail(path, content_type), -1);
+            pages.push((path.clone(), page));
+
+            if Some(id.as_str()) == attachment_id {
+                start = Some(index);
+            }
+        }
+
+        let stack = Stack::new();
+        let grid_window = ScrolledWindow::builder()
+            .child(&grid)
+            .vexpand(true)
+            .build();
+        stack.add_named(&grid_window, Some("grid"));
+        stack.add_named(&carousel, Some("viewer"));
+
+        if media.is_empty() {
+            stack.add_named(&Label::new(Some("No media shared yet")), Some("empty"));
+            stack.set_visible_child_name("empty");
+        } else if start.is_none() {
+            stack.set_visible_child_name("grid");
+        }
+
+        let grid_button = Button::builder()
+            .icon_name("view-grid-symbolic")
+            .tooltip_text("All media")
+            .build();
+
+        let save_button = Button::builder()
+            .icon_name("document-save")
+            .tooltip_text("Save to…")
+            .build();
+
+        let header = HeaderBar::new();
+        header.pack_start(&grid_button);
+        header.pack_start(&save_button);
+
+        let vbox = Box_::new(Orientation::Vertical, 0);
+        vbox.append(&header);
+        vbox.append(&stack);
+
+        let window = Window::builder()
+            .transient_for(&self.window)
+            .modal(true)
+            .title(conversation.get_name())
+            .child(&vbox)
+            .build();
+
+        let gallery = Rc::new(Gallery {
+            window,
+            stack,
+            carousel,
+            pages,
+            chooser: RefCell::new(None)
+        });
+
+        grid.connect_child_activated(clone!(@strong gallery => move |_, child| {
+            gallery.show_page(child.index() as usize);
+        }));
+
+        grid_button.connect_clicked(clone!(@strong gallery => move |_| {
+            if !gallery.pages.is_empty() {
+                gallery.stack.set_visible_child_name("grid");
+            }
+        }));
+
+        save_button.connect_clicked(clone!(@strong self as app, @strong gallery => move |_| {
+            gallery.clone().save_current(app.clone());
+        }));
+
+        gallery.window.fullscreen();
+        gallery.window.show();
+
+        // Pages can only be scrolled to once they've been allocated
+        if let Some(start) = start {
+            glib::idle_add_local_once(clone!(@strong gallery => move || {
+                gallery.show_page(start);
+            }));
+        }
+    }
+}
+
+impl Gallery {
+    fn show_page(&self, index: usize) {
+        if let Some((_, page)) = self.pages.get(index) {
+            self.stack.set_visible_child_name("viewer");
+            self.carousel.scroll_to_full(page, 0);
+        }
+    }
+
+    /// Copy the item on screen to wherever the user picks
+    fn save_current(self: Rc<Self>, app: Rc<App>) {
+        if self.stack.visible_child_name().as_deref() != Some("viewer") {
+            return;
+        }
+
+        let source = match self.pages.get(self.carousel.position().round() as usize) {
+            Some((path, _)) => path.clone(),
+            None => return
+        };
+
+        let chooser = FileChooserNative::new(
+            Some("Save to…"),
+            Some(&self.window),
+            FileChooserAction::Save,
+            Some("Save"),
+            Some("Cancel")
+        );
+
+        if let Some(name) = source.file_name().and_then(|name| name.to_str()) {
+            chooser.set_current_name(name);
+        }
+
+        chooser.connect_response(clone!(@weak self as gallery => move |chooser, response| {
+            if response == ResponseType::Accept {
+                if let Some(destination) = chooser.file().and_then(|file| file.path()) {
+                    if let Err(e) = std::fs::copy(&source, &destination) {
+                        app.flash_status(&format!("Couldn't save file: {}", e));
+                    }
+                }
+            }
+
+            gallery.chooser.replace(None);
+        }));
+
+        chooser.show();
+        self.chooser.replace(Some(chooser));
+    }
+}
+
+/// A picture zoomed by pinching or ctrl+scrolling. Swiping to the next
+/// page is turned off while zoomed in so dragging pans instead.
+fn zoomable_picture(path: &Path, carousel: &adw::Carousel) -> ScrolledWindow {
+    let picture = Picture::builder()
+        .file(&gio::File::for_path(path))
+        .can_shrink(true)
+        .build();
+
+    let scrolled = ScrolledWindow::builder()
+        .child(&picture)
+        .hexpand(true)
+        .vexpand(true)
+        .build();
+
+    let zoom = Rc::new(Cell::new(MIN_ZOOM));
+    let set_zoom = Rc::new(clone!(@weak scrolled, @weak picture, @weak carousel, @strong zoom => move |value: f64| {
+        let value = value.clamp(MIN_ZOOM, MAX_ZOOM);
+        zoom.set(value);
+
+        if value <= MIN_ZOOM {
+            picture.set_size_request(-1, -1);
+        } else {
+            picture.set_size_request(
+                (scrolled.width() as f64 * value) as i32,
+                (scrolled.height() as f64 * value) as i32
+            );
+        }
+
+        carousel.set_interactive(value <= MIN_ZOOM);
+    }));
+
+    let pinch = GestureZoom::new();
+    let pinch_start = Rc::new(Cell::new(MIN_ZOOM));
+    pinch.connect_begin(clone!(@strong zoom, @strong pinch_start => move |_, _| {
+        pinch_start.set(zoom.get());
+    }));
+    pinch.connect_scale_changed(clone!(@strong set_zoom, @strong pinch_start => move |_, scale| {
+        set_zoom(pinch_start.get() * scale);
+    }));
+    scrolled.add_controller(&pinch);
+
+    let scroll = EventControllerScroll::new(EventControllerScrollFlags::VERTICAL);
+    scroll.connect_scroll(clone!(@strong set_zoom, @strong zoom => move |controller, _, dy| {
+        if controller.current_event_state().contains(ModifierType::CONTROL_MASK) {
+            set_zoom(zoom.get() * (1.0 - dy * 0.1));
+            Inhibit(true)
+        } else {
+            Inhibit(false)
+        }
+    }));
+    scrolled.add_controller(&scroll);
+
+    scrolled
+}
+
+fn thumbnail(path: &Path, content_type: &str) -> Widget {
+    if content_type.starts_with("video/") {
+        return Image::builder()
+            .icon_name("video-x-generic")
+            .pixel_size(THUMBNAIL_SIZE / 2)
+            .width_request(THUMBNAIL_SIZE)
+            .height_request(THUMBNAIL_SIZE)
+            .build()
+            .upcast();
+    }
+
+    Picture::builder()
+        .file(&gio::File::for_path(path))
+        .can_shrink(true)
+        .width_request(THUMBNAIL_SIZE)
+        .height_request(THUMBNAIL_SIZE)
+        .build()
+        .upcast()
+}
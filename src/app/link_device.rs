@@ -1,30 +1,37 @@
 use gtk::prelude::*;
-use gtk::{Button, Box as Box_, Picture, Label, Entry, Orientation};
+use gtk::{Button, Box as Box_, CheckButton, DropDown, Entry, InputPurpose, Label, LinkButton, Orientation,
+          PasswordEntry, Picture, Revealer};
+use gtk::gdk;
 use gtk::glib::{self, clone, MainContext};
 use async_std::channel::Sender;
 
 use std::rc::Rc;
+use std::cell::{Cell, RefCell};
 
 use qrcode::QrCode;
-use image::Luma;
+use image::Rgb;
 
 use signald::types::{SignaldTypes, FinishLinkRequestV1, GenerateLinkingURIRequestV1, LinkingURIV1};
 
+use signal_rs_core::{Error, Result};
+use signal_rs_core::phone::{to_e164, COUNTRIES};
+use signal_rs_core::signald_bridge::CancelHandle;
+
 use crate::app::App;
 
-async fn handle_clicked(app: Rc<App>) -> Result<LinkingURIV1, &'static str> {
-    let linking = app.dispatch(
+async fn generate_linking_uri(app: Rc<App>) -> Result<LinkingURIV1> {
+    let linking = app.client.dispatch(
         "generate_linking_uri",
         SignaldTypes::GenerateLinkingURIRequestV1(
             GenerateLinkingURIRequestV1::default()
         )
-    ).await;
+    ).await?;
 
 
     if let SignaldTypes::LinkingURIV1(linking) = linking {
         Ok(linking)
     } else {
-        Err("Error generating linking uri")
+        Err(Error::unexpected("generate_linking_uri"))
     }
 }
 
@@ -37,6 +44,13 @@ pub fn build_ui(app: Rc<App>, sender: Sender<String>) -> Box_ {
         .halign(gtk::Align::Start)
         .build();
 
+    // What the primary device lists us as
+    let device_name = Entry::builder()
+        .text(&glib::host_name())
+        .placeholder_text("Device name")
+        .halign(gtk::Align::Center)
+        .build();
+
     let hbox = Box_::builder()
         .orientation(Orientation::Horizontal)
         .spacing(3)
@@ -47,24 +61,36 @@ pub fn build_ui(app: Rc<App>, sender: Sender<String>) -> Box_ {
         .label("Register new number")
         .build();
 
-    button1.connect_clicked(clone!(@strong app => move |_| {
-        app.update_ui(&register_ui(app.clone()), "register");
+    button1.connect_clicked(clone!(@strong app, @strong sender => move |_| {
+        app.update_ui(&register_ui(app.clone(), sender.clone()), "register");
     }));
 
     let button2 = Button::builder()
         .label("Link device")
         .build();
 
-    button2.connect_clicked(clone!(@strong app, @weak button1 => 
+    button2.connect_clicked(clone!(@strong app, @weak button1, @weak device_name => 
         move |button| {
             let main_context = MainContext::default();
+            let name = match device_name.text().trim() {
+                "" => glib::host_name().to_string(),
+                name => name.to_owned()
+            };
 
             main_context.spawn_local(clone!(@weak button, @weak button1, @strong app, @strong sender => 
                 async move {
                     button.set_sensitive(false);
                     button1.set_sensitive(false);
-                    let linking = handle_clicked(app.clone()).await.unwrap();
-                    app.update_ui(&link_ui(app.clone(), linking, sender), "link");
+                    match generate_linking_uri(app.clone()).await {
+                        Ok(linking) => {
+                            app.update_ui(&link_ui(app.clone(), linking, name, sender), "link");
+                        },
+                        Err(e) => {
+                            app.show_error(&e);
+                            button.set_sensitive(true);
+                            button1.set_sensitive(true);
+                        }
+                    }
                 }
             ));
         }
@@ -73,56 +99,242 @@ pub fn build_ui(app: Rc<App>, sender: Sender<String>) -> Box_ {
     hbox.append(&button1);
     hbox.append(&button2);
 
+    // Adding another account, so there's somewhere to go back to
+    if !app.client.account().is_empty() {
+        let back = Button::builder()
+            .label("Back")
+            .build();
+
+        back.connect_clicked(clone!(@strong app, @strong sender => move |_| {
+            sender.close();
+            app.update_ui(&app.clone().main_view_ui(), "main_view");
+        }));
+
+        hbox.prepend(&back);
+    }
+
     vbox.append(&label);
+    vbox.append(&device_name);
     vbox.append(&hbox);
 
     vbox
 }
 
-async fn finish_link(app: Rc<App>, session_id: String, sender: Sender<String>) {
-    let account = app.dispatch(
-        "finish_link",
-        SignaldTypes::FinishLinkRequestV1(
-            FinishLinkRequestV1 {
-                session_id: Some(session_id),
-                device_name: Some("signal-rs-test".to_owned())
+/// The widgets linking updates while it waits for the QR code to be
+/// scanned
+struct LinkView {
+    label: Label,
+    image: Picture,
+    retry: Button,
+    // The finish_link request currently waiting, and whether the user gave
+    // up, which can happen while a new URI is being generated
+    cancel: RefCell<Option<CancelHandle>>,
+    cancelled: Cell<bool>,
+    // Linking stopped on an error, so nothing is waiting to be cancelled
+    failed: Cell<bool>
+}
+
+impl LinkView {
+    fn show_code(&self, uri: &str) {
+        self.label.set_label("Scan QR code on primary device");
+        self.image.set_paintable(qr_texture(uri).as_ref());
+        self.image.show();
+        self.retry.hide();
+    }
+
+    fn show_error(&self, error: &Error) {
+        self.failed.set(true);
+        self.label.set_label(&format!("Linking failed: {}", error));
+        self.image.hide();
+        self.retry.show();
+    }
+}
+
+// Linking URIs expire if nobody scans them, so keep swapping in fresh ones
+// until the code is scanned or the user cancels
+async fn finish_link(app: Rc<App>, mut session_id: String, device_name: String, sender: Sender<String>, view: Rc<LinkView>) {
+    loop {
+        let request = app.client.request(
+            "finish_link",
+            SignaldTypes::FinishLinkRequestV1(
+                FinishLinkRequestV1 {
+                    session_id: Some(session_id.clone()),
+                    device_name: Some(device_name.clone())
+                }
+            )
+        ).await;
+
+        let account = match request {
+            Ok(request) => {
+                view.cancel.replace(Some(request.cancel_handle()));
+                let account = request.response().await;
+                view.cancel.replace(None);
+                account
+            },
+            Err(e) => Err(e)
+        };
+
+        if view.cancelled.get() {
+            break;
+        }
+
+        match account {
+            Ok(SignaldTypes::AccountV1(account)) => {
+                let account_id = match account.account_id {
+                    Some(account_id) => account_id,
+                    None => {
+                        view.show_error(&Error::Protocol("finish_link didn't say which account was linked".to_owned()));
+                        return;
+                    }
+                };
+
+                // Nothing's waiting for the account any more
+                if sender.send(account_id).await.is_err() {
+                    view.show_error(&Error::AccountSetup);
+                }
+                return;
+            },
+            Ok(_) => {
+                view.show_error(&Error::unexpected("finish_link"));
+                return;
+            },
+            Err(Error::Cancelled) => break,
+            Err(e) if is_expired(&e) => {
+                match generate_linking_uri(app.clone()).await {
+                    Ok(linking) if !view.cancelled.get() => {
+                        session_id = match linking.session_id {
+                            Some(session_id) => session_id,
+                            None => {
+                                view.show_error(&Error::unexpected("generate_linking_uri"));
+                                return;
+                            }
+                        };
+                        view.show_code(linking.uri.as_deref().unwrap_or_default());
+                    },
+                    Ok(_) => break,
+                    Err(e) => {
+                        view.show_error(&e);
+                        return;
+                    }
+                }
+            },
+            Err(e) => {
+                view.show_error(&e);
+                return;
             }
-        )
-    ).await;
+        }
+    }
+
+    // Back to the start so linking can be tried again
+    app.update_ui(&build_ui(app.clone(), sender), "new_device");
+}
 
-    if let SignaldTypes::AccountV1(account) = account {
-        sender.send(account.account_id.unwrap()).await.expect("Channel broken");
-        //app.update_ui(&load_app::finish_link_ui(app.clone(), account));
+// signald gives up waiting for the scan with a timeout of its own
+fn is_expired(error: &Error) -> bool {
+    match error {
+        Error::Timeout(_) => true,
+        Error::Signald(message) => message.contains("Timeout") || message.contains("expired"),
+        _ => false
     }
 }
 
-fn link_ui(app: Rc<App>, linking: LinkingURIV1, sender: Sender<String>) -> Box_ {
+fn link_ui(app: Rc<App>, linking: LinkingURIV1, device_name: String, sender: Sender<String>) -> Box_ {
     let vbox = Box_::new(Orientation::Vertical, 5);
 
     let label = Label::builder()
-        .label("Scan QR code on primary device")
         .css_classes(vec!["label1".to_owned()])
         .halign(gtk::Align::Center)
+        .wrap(true)
         .build();
 
-    let code = QrCode::new(linking.uri.as_ref().unwrap()).unwrap();
-    let image = code.render::<Luma<u8>>().build();
-    image.save("/tmp/qrcode.png").unwrap();
+    let image = Picture::builder()
+        .can_shrink(true)
+        .build();
 
-    let image = Picture::for_filename("/tmp/qrcode.png");
+    let retry = Button::builder()
+        .label("Try again")
+        .halign(gtk::Align::Center)
+        .visible(false)
+        .build();
+
+    let cancel = Button::builder()
+        .label("Cancel")
+        .halign(gtk::Align::Center)
+        .build();
 
-    let main_context = MainContext::default();
-    main_context.spawn_local(clone!(@strong app => async move {
-        finish_link(app, linking.session_id.unwrap(), sender).await;
+    let view = Rc::new(LinkView {
+        label,
+        image,
+        retry,
+        cancel: RefCell::new(None),
+        cancelled: Cell::new(false),
+        failed: Cell::new(false)
+    });
+    view.show_code(linking.uri.as_deref().unwrap_or_default());
+
+    cancel.connect_clicked(clone!(@strong app, @strong view, @strong sender => move |_| {
+        view.cancelled.set(true);
+        if let Some(handle) = view.cancel.take() {
+            handle.cancel();
+        } else if view.failed.get() {
+            // finish_link has already stopped, so it won't go back itself
+            app.update_ui(&build_ui(app.clone(), sender.clone()), "new_device");
+        }
     }));
 
-    vbox.append(&label);
-    vbox.append(&image);
+    view.retry.connect_clicked(clone!(@strong app, @strong sender, @strong device_name => move |retry| {
+        retry.set_sensitive(false);
+        MainContext::default().spawn_local(clone!(@strong app, @strong sender, @strong device_name, @weak retry => async move {
+            match generate_linking_uri(app.clone()).await {
+                Ok(linking) => app.update_ui(&link_ui(app.clone(), linking, device_name, sender), "link"),
+                Err(e) => {
+                    app.show_error(&e);
+                    retry.set_sensitive(true);
+                }
+            }
+        }));
+    }));
+
+    match linking.session_id {
+        Some(session_id) => {
+            let main_context = MainContext::default();
+            main_context.spawn_local(clone!(@strong app, @strong view => async move {
+                finish_link(app, session_id, device_name, sender, view).await;
+            }));
+        },
+        None => view.show_error(&Error::unexpected("generate_linking_uri"))
+    }
+
+    vbox.append(&view.label);
+    vbox.append(&view.image);
+    vbox.append(&view.retry);
+    vbox.append(&cancel);
 
     vbox
 }
 
-fn register_ui(app: Rc<App>) -> Box_ {
+// Rendered straight into memory, the code never touches the disk
+fn qr_texture(uri: &str) -> Option<gdk::Texture> {
+    let code = QrCode::new(uri).ok()?;
+    let image = code.render::<Rgb<u8>>()
+        .min_dimensions(300, 300)
+        .build();
+
+    let (width, height) = image.dimensions();
+    let bytes = glib::Bytes::from_owned(image.into_raw());
+
+    Some(gdk::MemoryTexture::new(
+        width as i32,
+        height as i32,
+        gdk::MemoryFormat::R8g8b8,
+        &bytes,
+        width as usize * 3
+    ).upcast())
+}
+
+const CAPTCHA_URL: &str = "https://signalcaptchas.org/registration/generate.html";
+
+fn register_ui(app: Rc<App>, sender: Sender<String>) -> Box_ {
     // The container container.
     let vbox = Box_::new(Orientation::Vertical, 5);
 
@@ -132,7 +344,44 @@ fn register_ui(app: Rc<App>) -> Box_ {
         .halign(gtk::Align::Start)
         .build();
 
+    let names: Vec<String> = COUNTRIES.iter().map(|(name, code)| {
+        format!("{} (+{})", name, code)
+    }).collect();
+    let names: Vec<&str> = names.iter().map(|name| name.as_str()).collect();
+    let country = DropDown::from_strings(&names);
+    if let Some(position) = COUNTRIES.iter().position(|(name, _)| *name == "United States") {
+        country.set_selected(position as u32);
+    }
+
     let entry = Entry::builder()
+        .placeholder_text("Phone number")
+        .input_purpose(InputPurpose::Phone)
+        .hexpand(true)
+        .build();
+
+    let number_box = Box_::new(Orientation::Horizontal, 3);
+    number_box.append(&country);
+    number_box.append(&entry);
+
+    let voice = CheckButton::with_label("Call me instead of texting");
+
+    // Only asked for once Signal wants one
+    let captcha = Entry::builder()
+        .placeholder_text("Paste the captcha token")
+        .build();
+
+    let captcha_box = Box_::new(Orientation::Vertical, 3);
+    captcha_box.append(&Label::builder()
+        .label("Solve the captcha, then copy the link from \"Open Signal\" here")
+        .wrap(true)
+        .halign(gtk::Align::Start)
+        .build());
+    captcha_box.append(&LinkButton::with_label(CAPTCHA_URL, "Open captcha"));
+    captcha_box.append(&captcha);
+
+    let captcha_revealer = Revealer::builder()
+        .child(&captcha_box)
+        .reveal_child(false)
         .build();
 
     let hbox = Box_::builder()
@@ -145,15 +394,149 @@ fn register_ui(app: Rc<App>) -> Box_ {
         .label("Back")
         .build();
 
+    button1.connect_clicked(clone!(@strong app, @strong sender => move |_| {
+        app.update_ui(&build_ui(app.clone(), sender.clone()), "new_device");
+    }));
+
     let button2 = Button::builder()
         .label("Next")
         .build();
 
+    button2.connect_clicked(clone!(@strong app, @strong sender, @weak country, @weak entry, @weak voice,
+                                   @weak captcha, @weak captcha_revealer => move |button| {
+        let calling_code = COUNTRIES[country.selected() as usize].1;
+        let number = match to_e164(calling_code, &entry.text()) {
+            Some(number) => number,
+            None => {
+                app.flash_status("That doesn't look like a phone number");
+                return;
+            }
+        };
+
+        // signald wants the token without the link around it
+        let token = captcha.text().trim().trim_start_matches("signalcaptcha://").to_owned();
+        let token = if token.is_empty() { None } else { Some(token) };
+
+        let main_context = MainContext::default();
+        main_context.spawn_local(clone!(@strong app, @strong sender, @weak button, @weak voice, @weak captcha_revealer =>
+            async move {
+                button.set_sensitive(false);
+                match app.client.register(&number, voice.is_active(), token).await {
+                    Ok(()) => {
+                        app.update_ui(&verify_ui(app.clone(), number, sender), "verify");
+                        return;
+                    },
+                    Err(e @ Error::CaptchaRequired(_)) => {
+                        captcha_revealer.set_reveal_child(true);
+                        app.show_error(&e);
+                    },
+                    Err(e) => app.show_error(&e)
+                }
+                button.set_sensitive(true);
+            }
+        ));
+    }));
+
+    hbox.append(&button1);
+    hbox.append(&button2);
+
+    vbox.append(&label);
+    vbox.append(&number_box);
+    vbox.append(&voice);
+    vbox.append(&captcha_revealer);
+    vbox.append(&hbox);
+
+    vbox
+}
+
+fn verify_ui(app: Rc<App>, number: String, sender: Sender<String>) -> Box_ {
+    let vbox = Box_::new(Orientation::Vertical, 5);
+
+    let label = Label::builder()
+        .label(&format!("Enter the code sent to {}", number))
+        .css_classes(vec!["label1".to_owned()])
+        .halign(gtk::Align::Start)
+        .build();
+
+    let code = Entry::builder()
+        .placeholder_text("123-456")
+        .input_purpose(InputPurpose::Digits)
+        .build();
+
+    // Only asked for if the number has a registration lock
+    let pin = PasswordEntry::builder()
+        .placeholder_text("Signal PIN")
+        .show_peek_icon(true)
+        .build();
+
+    let pin_box = Box_::new(Orientation::Vertical, 3);
+    pin_box.append(&Label::builder()
+        .label("This number is protected by a registration lock")
+        .halign(gtk::Align::Start)
+        .build());
+    pin_box.append(&pin);
+
+    let pin_revealer = Revealer::builder()
+        .child(&pin_box)
+        .reveal_child(false)
+        .build();
+
+    let hbox = Box_::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(3)
+        .halign(gtk::Align::End)
+        .build();
+
+    let button1 = Button::builder()
+        .label("Back")
+        .build();
+
+    button1.connect_clicked(clone!(@strong app, @strong sender => move |_| {
+        app.update_ui(&register_ui(app.clone(), sender.clone()), "register");
+    }));
+
+    let button2 = Button::builder()
+        .label("Verify")
+        .build();
+
+    button2.connect_clicked(clone!(@strong app, @strong sender, @strong number, @weak code, @weak pin,
+                                   @weak pin_revealer => move |button| {
+        let code = code.text().to_string();
+        let pin_code = if pin_revealer.reveals_child() && !pin.text().is_empty() {
+            Some(pin.text().to_string())
+        } else {
+            None
+        };
+
+        let main_context = MainContext::default();
+        main_context.spawn_local(clone!(@strong app, @strong sender, @strong number, @weak button, @weak pin_revealer =>
+            async move {
+                button.set_sensitive(false);
+                match app.client.verify(&number, &code, pin_code).await {
+                    Ok(account) => {
+                        // The same hand off as linking
+                        if sender.send(account).await.is_err() {
+                            app.show_error(&Error::AccountSetup);
+                        }
+                        return;
+                    },
+                    Err(e @ Error::RegistrationLocked(_)) => {
+                        pin_revealer.set_reveal_child(true);
+                        app.show_error(&e);
+                    },
+                    Err(e) => app.show_error(&e)
+                }
+                button.set_sensitive(true);
+            }
+        ));
+    }));
+
     hbox.append(&button1);
     hbox.append(&button2);
 
     vbox.append(&label);
-    vbox.append(&entry);
+    vbox.append(&code);
+    vbox.append(&pin_revealer);
     vbox.append(&hbox);
 
     vbox
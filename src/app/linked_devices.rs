@@ -0,0 +1,248 @@
+use gtk::prelude::*;
+use gtk::{Align, Box as Box_, Button, Entry, FileChooserAction, FileChooserNative, HeaderBar, Label, Orientation,
+          ResponseType, ScrolledWindow, PolicyType};
+use gtk::glib::{self, clone, MainContext};
+
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+
+use signald::types::DeviceInfoV1;
+
+use signal_rs_core::Result;
+use signal_rs_core::client::PRIMARY_DEVICE_ID;
+
+use crate::app::{main_view, App};
+
+impl App {
+    /// The devices linked to the active account, fetched from signald
+    pub async fn show_linked_devices(self: Rc<App>) {
+        self.active.replace(None);
+        self.update_ui(&main_view::loading(), "loading");
+
+        match self.load_devices().await {
+            Ok((devices, device_id)) => {
+                self.update_ui(&self.clone().linked_devices_ui(devices, device_id), "linked_devices");
+            },
+            Err(e) => {
+                self.show_error(&e);
+                self.update_ui(&self.clone().main_view_ui(), "main_view");
+            }
+        }
+    }
+
+    async fn load_devices(&self) -> Result<(Vec<DeviceInfoV1>, Option<i64>)> {
+        let devices = self.client.linked_devices().await?;
+        let device_id = self.client.device_id().await?;
+
+        Ok((devices, device_id))
+    }
+
+    fn linked_devices_ui(self: Rc<App>, devices: Vec<DeviceInfoV1>, device_id: Option<i64>) -> ScrolledWindow {
+        let vbox = Box_::new(Orientation::Vertical, 5);
+        let primary = device_id == Some(PRIMARY_DEVICE_ID);
+
+        let back = Button::builder()
+            .icon_name("go-previous")
+            .build();
+
+        back.connect_clicked(clone!(@strong self as app => move |_| {
+            app.update_ui(&app.clone().main_view_ui(), "main_view");
+        }));
+
+        let header = HeaderBar::builder()
+            .title_widget(&Label::new(Some("Linked devices")))
+            .show_title_buttons(true)
+            .build();
+        header.pack_start(&back);
+
+        vbox.append(&header);
+
+        for device in devices {
+            vbox.append(&self.clone().device_row(device, device_id, primary));
+        }
+
+        // Only the primary device can link others
+        if primary {
+            vbox.append(&self.clone().add_device_ui());
+        }
+
+        ScrolledWindow::builder()
+            .hscrollbar_policy(PolicyType::Never)
+            .child(&vbox)
+            .build()
+    }
+
+    fn device_row(self: Rc<App>, device: DeviceInfoV1, device_id: Option<i64>, primary: bool) -> Box_ {
+        let id = device.id.unwrap_or_default();
+        let this_device = Some(id) == device_id;
+
+        let name = match device.name.as_deref() {
+            Some(name) if !name.is_empty() => name.to_owned(),
+            _ => format!("Device {}", id)
+        };
+
+        let text = Box_::new(Orientation::Vertical, 0);
+        text.set_hexpand(true);
+        text.append(&Label::builder()
+            .label(&if this_device { format!("{} (this device)", name) } else { name })
+            .css_classes(vec!["label1".to_owned()])
+            .halign(Align::Start)
+            .build());
+        text.append(&Label::builder()
+            .label(&format!("Linked {}", format_time(device.created)))
+            .halign(Align::Start)
+            .build());
+        text.append(&Label::builder()
+            .label(&format!("Last seen {}", format_time(device.last_seen)))
+            .halign(Align::Start)
+            .build());
+
+        let row = Box_::builder()
+            .orientation(Orientation::Horizontal)
+            .spacing(15)
+            .css_classes(vec!["linkedDevice".to_owned()])
+            .build();
+        row.append(&text);
+
+        // The primary device can't be unlinked, nor can we unlink ourselves
+        if primary && !this_device && id != PRIMARY_DEVICE_ID {
+            let unlink = Button::builder()
+                .label("Unlink")
+                .valign(Align::Center)
+                .build();
+
+            unlink.connect_clicked(clone!(@strong self as app => move |unlink| {
+                // Ask once more before cutting the device off
+                if unlink.label().as_deref() != Some("Really unlink?") {
+                    unlink.set_label("Really unlink?");
+                    return;
+                }
+
+                unlink.set_sensitive(false);
+                MainContext::default().spawn_local(clone!(@strong app => async move {
+                    if let Err(e) = app.client.unlink_device(id).await {
+                        app.show_error(&e);
+                    }
+                    app.show_linked_devices().await;
+                }));
+            }));
+
+            row.append(&unlink);
+        }
+
+        row
+    }
+
+    /// Link a new device by pasting its linking URI, or from a picture of
+    /// its QR code
+    fn add_device_ui(self: Rc<App>) -> Box_ {
+        let uri = Entry::builder()
+            .placeholder_text("sgnl://linkdevice?…")
+            .hexpand(true)
+            .build();
+
+        let scan = Button::builder()
+            .icon_name("camera-photo-symbolic")
+            .tooltip_text("Read QR code from a picture")
+            .build();
+
+        scan.connect_clicked(clone!(@strong self as app, @weak uri => move |_| {
+            app.choose_qr_image(&uri);
+        }));
+
+        let add = Button::builder()
+            .label("Link device")
+            .build();
+
+        add.connect_clicked(clone!(@strong self as app, @weak uri => move |add| {
+            let text = uri.text().to_string();
+            add.set_sensitive(false);
+
+            MainContext::default().spawn_local(clone!(@strong app, @weak add => async move {
+                match app.client.add_device(&text).await {
+                    Ok(()) => app.show_linked_devices().await,
+                    Err(e) => {
+                        app.show_error(&e);
+                        add.set_sensitive(true);
+                    }
+                }
+            }));
+        }));
+
+        let hbox = Box_::new(Orientation::Horizontal, 5);
+        hbox.append(&uri);
+        hbox.append(&scan);
+        hbox.append(&add);
+
+        let vbox = Box_::new(Orientation::Vertical, 5);
+        vbox.append(&Label::builder()
+            .label("Link a new device")
+            .css_classes(vec!["label1".to_owned()])
+            .halign(Align::Start)
+            .build());
+        vbox.append(&hbox);
+
+        vbox
+    }
+
+    fn choose_qr_image(self: Rc<App>, uri: &Entry) {
+        let chooser = FileChooserNative::new(
+            Some("Picture of QR code"),
+            Some(&self.window),
+            FileChooserAction::Open,
+            Some("Open"),
+            Some("Cancel")
+        );
+
+        let filter = gtk::FileFilter::new();
+        filter.add_pixbuf_formats();
+        chooser.add_filter(&filter);
+
+        // Native choosers close once nothing holds on to them
+        let open: Rc<RefCell<Option<FileChooserNative>>> = Rc::new(RefCell::new(None));
+
+        chooser.connect_response(clone!(@strong self as app, @weak uri, @strong open => move |chooser, response| {
+            if response == ResponseType::Accept {
+                let decoded = chooser.file()
+                    .and_then(|file| file.path())
+                    .and_then(|path| read_qr_code(&path));
+
+                match decoded {
+                    Some(text) => uri.set_text(&text),
+                    None => app.flash_status("Couldn't find a QR code in that picture")
+                }
+            }
+
+            open.replace(None);
+        }));
+
+        chooser.show();
+        open.replace(Some(chooser));
+    }
+}
+
+fn read_qr_code(path: &Path) -> Option<String> {
+    let image = image::open(path).ok()?.to_luma8();
+    let mut prepared = rqrr::PreparedImage::prepare(image);
+
+    prepared.detect_grids().iter().find_map(|grid| {
+        grid.decode().ok().map(|(_, content)| content)
+    })
+}
+
+// signald gives times in milliseconds
+fn format_time(millis: Option<i64>) -> String {
+    let when = millis.map(|millis| {
+        glib::DateTime::from_unix_local(millis / 1000)
+            .and_then(|when| when.format("%b %e %Y, %H:%M"))
+            .map(|when| when.to_string())
+            .unwrap_or_default()
+    }).unwrap_or_default();
+
+    if when.is_empty() {
+        "unknown".to_owned()
+    } else {
+        when
+    }
+}
@@ -1,12 +1,45 @@
 use gtk::prelude::*;
-use gtk::Label;
+use gtk::{Box as Box_, Button, Label, Orientation};
 use gtk::glib::{self, clone, MainContext};
 
 use signald::types::{AccountV1, SignaldTypes, SubscribeRequestV1};
 
+use signal_rs_core::Error;
+
 use crate::app::App;
 use std::rc::Rc;
 
+pub fn load_error_ui(app: Rc<App>, error: &Error) -> Box_ {
+    let vbox = Box_::builder()
+        .orientation(Orientation::Vertical)
+        .spacing(5)
+        .valign(gtk::Align::Center)
+        .build();
+
+    let label = Label::builder()
+        .label(&format!("Couldn't load account\n{}", error))
+        .css_classes(vec!["label1".to_owned()])
+        .halign(gtk::Align::Center)
+        .wrap(true)
+        .build();
+
+    let retry = Button::builder()
+        .label("Retry")
+        .halign(gtk::Align::Center)
+        .build();
+
+    retry.connect_clicked(clone!(@strong app => move |_| {
+        MainContext::default().spawn_local(clone!(@strong app => async move {
+            app.initialize().await;
+        }));
+    }));
+
+    vbox.append(&label);
+    vbox.append(&retry);
+
+    vbox
+}
+
 pub fn finish_link_ui(app: Rc<App>, account: AccountV1) -> Label {
     let label = Label::builder()
         .label("Finished linking!")
@@ -16,7 +49,7 @@ pub fn finish_link_ui(app: Rc<App>, account: AccountV1) -> Label {
 
     let main_context = MainContext::default();
     main_context.spawn_local(clone!(@strong app => async move {
-        app.dispatch(
+        let response = app.client.dispatch(
             "subscribe",
             SignaldTypes::SubscribeRequestV1(
                 SubscribeRequestV1 {
@@ -24,6 +57,10 @@ pub fn finish_link_ui(app: Rc<App>, account: AccountV1) -> Label {
                 }
             )
         ).await;
+
+        if let Err(e) = response {
+            app.show_error(&e);
+        }
     }));
 
     label
@@ -1,11 +1,12 @@
 use gtk::prelude::*;
-use gtk::{Button, Box as Box_, Label, HeaderBar, Orientation, ScrolledWindow, Picture, PolicyType};
-use gtk::glib::clone;
+use gtk::{Button, Box as Box_, Label, HeaderBar, MenuButton, Orientation, ScrolledWindow, Picture, PolicyType, Popover};
+use gtk::glib::{clone, MainContext};
 use adw::Avatar;
 use std::rc::Rc;
 
+use signal_rs_core::conversation::ConversationType;
+
 use crate::app::App;
-use crate::app::conversation::ConversationType;
 
 impl App {
     pub fn main_view_ui(self: Rc<App>) -> ScrolledWindow {
@@ -15,10 +16,12 @@ impl App {
             .name("Signal")
             .show_title_buttons(true)
             .build();
+        header.pack_end(&self.clone().app_menu());
+        header.pack_start(&self.clone().account_switcher());
 
         vbox.append(&header);
 
-        self.conversations.borrow().iter().for_each(|conversation| {
+        self.client.conversations().iter().for_each(|conversation| {
             let label = Label::builder()
                 .label(&conversation.name)
                 .css_classes(vec!["label1".to_owned()])
@@ -51,8 +54,20 @@ impl App {
                 pic.paintable()
             }).unwrap_or(None).as_ref());
 
+            let text = Box_::new(Orientation::Vertical, 0);
+            text.set_valign(gtk::Align::Center);
+            text.append(&label);
+
+            if let Some(typing) = self.typing_text(conversation) {
+                text.append(&Label::builder()
+                    .label(&typing)
+                    .css_classes(vec!["typing".to_owned()])
+                    .halign(gtk::Align::Start)
+                    .build());
+            }
+
             msg_box.append(&avatar);
-            msg_box.append(&label);
+            msg_box.append(&text);
 
             if *conversation.new_msgs.borrow() > 0 {
                 let msg_notify = Label::builder()
@@ -83,6 +98,74 @@ impl App {
             .child(&vbox)
             .build()
     }
+
+    /// Every account with its unread count, to switch between them or add
+    /// another
+    fn account_switcher(self: Rc<App>) -> MenuButton {
+        let active = self.client.account();
+        let vbox = Box_::new(Orientation::Vertical, 5);
+        let popover = Popover::builder()
+            .child(&vbox)
+            .build();
+
+        // Messages waiting on the accounts not on screen
+        let mut elsewhere = 0;
+
+        for account in self.client.accounts() {
+            let unread = self.client.unread_count(&account);
+            let hbox = Box_::new(Orientation::Horizontal, 15);
+            hbox.append(&Label::new(Some(&account)));
+
+            if unread > 0 {
+                hbox.append(&Label::builder()
+                    .label(&format!("+{}", unread))
+                    .css_classes(vec!["newMsg".to_owned()])
+                    .build());
+            }
+
+            let button = Button::builder()
+                .child(&hbox)
+                .build();
+
+            if account == active {
+                button.set_sensitive(false);
+            } else {
+                elsewhere += unread;
+                button.connect_clicked(clone!(@strong self as app, @weak popover => move |_| {
+                    popover.popdown();
+                    MainContext::default().spawn_local(clone!(@strong app, @strong account => async move {
+                        app.switch_account(account).await;
+                    }));
+                }));
+            }
+
+            vbox.append(&button);
+        }
+
+        let add = Button::builder()
+            .label("Add account")
+            .build();
+
+        add.connect_clicked(clone!(@strong self as app, @weak popover => move |_| {
+            popover.popdown();
+            MainContext::default().spawn_local(clone!(@strong app => async move {
+                app.add_account().await;
+            }));
+        }));
+
+        vbox.append(&add);
+
+        let label = if elsewhere > 0 {
+            format!("{} (+{})", active, elsewhere)
+        } else {
+            active
+        };
+
+        MenuButton::builder()
+            .label(&label)
+            .popover(&popover)
+            .build()
+    }
 }
 
 pub fn loading() -> Label {
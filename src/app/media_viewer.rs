@@ -1,28 +1,245 @@
 use gtk::prelude::*;
-use gtk::{Box as Box_, Orientation, Picture, Video};
+use gtk::{Align, AppChooserDialog, Box as Box_, Button, DialogFlags, GestureClick, Image, Label, MediaControls, MediaFile,
+          Orientation, Picture, ResponseType, Video};
+use gtk::gdk_pixbuf::{Colorspace, Pixbuf};
+use gtk::gio;
+use gtk::glib::{self, clone, MainContext};
+use gtk::pango::EllipsizeMode;
 use std::rc::Rc;
+use std::path::{Path, PathBuf};
+
+use signal_rs_core::blurhash;
+use signal_rs_core::models::Attachment;
+use signal_rs_core::database;
 
-use crate::models::Attachment;
 use crate::app::App;
-use crate::database;
+
+const IMAGE_WIDTH: i32 = 300;
+const BLURHASH_WIDTH: i32 = 32;
 
 impl App {
     pub fn new_media_viewer(self: Rc<App>, attachment_list: &String) -> Box_ {
         let media_box = Box_::new(Orientation::Vertical, 3);
         attachment_list.split('\n').for_each(|id| {
-            let attachment = database::get_attachment(&self.db.lock().unwrap(), id);
+            let attachment = database::get_attachment(&self.client.db(), id);
             
             if let Some(attachment) = attachment {
-                if attachment.content_type.starts_with("image/") {
-                    media_box.append(&get_picture(attachment));
+                let path = match self.client.attachment_path(&attachment) {
+                    Some(path) => path,
+                    None => {
+                        media_box.append(&unavailable());
+                        return;
+                    }
+                };
+
+                let content_type = attachment.content_type.as_str();
+                if content_type.starts_with("image/") {
+                    let picture = get_picture(&path, &attachment);
+                    let click = GestureClick::new();
+                    let id = attachment.id.clone();
+                    click.connect_released(clone!(@strong self as app => move |_,_,_,_| {
+                        app.clone().show_media(&id);
+                    }));
+                    picture.add_controller(&click);
+
+                    media_box.append(&picture);
+                } else if content_type.starts_with("video/") {
+                    media_box.append(&get_video(&path));
+                } else if content_type.starts_with("audio/") {
+                    media_box.append(&get_audio(&path));
+                } else {
+                    media_box.append(&self.clone().get_file(attachment, path));
                 }
             }
         });
 
         media_box
     }
+
+    /// Name, size and an "Open with…" button for anything we can't show inline
+    fn get_file(self: Rc<App>, attachment: Attachment, path: PathBuf) -> Box_ {
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(&attachment.id)
+            .to_owned();
+        let size = std::fs::metadata(&path).ok().map(|metadata| {
+            glib::format_size(metadata.len()).to_string()
+        });
+
+        let tile = Box_::builder()
+            .orientation(Orientation::Horizontal)
+            .spacing(5)
+            .css_classes(vec!["fileAttachment".to_owned()])
+            .build();
+
+        tile.append(&Image::builder()
+            .gicon(&gio::content_type_get_icon(&attachment.content_type))
+            .pixel_size(32)
+            .build());
+
+        let details = Box_::builder()
+            .orientation(Orientation::Vertical)
+            .hexpand(true)
+            .build();
+
+        details.append(&Label::builder()
+            .label(&name)
+            .halign(Align::Start)
+            .ellipsize(EllipsizeMode::Middle)
+            .build());
+
+        details.append(&Label::builder()
+            .label(size.as_deref().unwrap_or("Unknown size"))
+            .halign(Align::Start)
+            .build());
+
+        let open = Button::builder()
+            .label("Open with…")
+            .valign(Align::Center)
+            .build();
+
+        open.connect_clicked(clone!(@strong self as app => move |_| {
+            app.clone().open_with(&path, &attachment.content_type);
+        }));
+
+        tile.append(&details);
+        tile.append(&open);
+
+        tile
+    }
+
+    fn open_with(self: Rc<App>, path: &Path, content_type: &str) {
+        let chooser = AppChooserDialog::for_content_type(
+            Some(&self.window),
+            DialogFlags::MODAL | DialogFlags::DESTROY_WITH_PARENT,
+            content_type
+        );
+
+        let file = gio::File::for_path(path);
+        chooser.connect_response(clone!(@strong self as app => move |chooser, response| {
+            if response == ResponseType::Ok {
+                if let Some(app_info) = chooser.app_info() {
+                    if let Err(e) = app_info.launch(&[file.clone()], None::<&gio::AppLaunchContext>) {
+                        app.flash_status(&format!("Couldn't open file: {}", e));
+                    }
+                }
+            }
+
+            chooser.close();
+        }));
+
+        chooser.show();
+    }
+}
+
+/// Shows the attachment's blurhash straight away and swaps in the image
+/// once it's been decoded off the main thread
+fn get_picture(path: &Path, attachment: &Attachment) -> Picture {
+    let picture = Picture::builder()
+        .can_shrink(true)
+        .keep_aspect_ratio(true)
+        .build();
+
+    // Reserve the image's space up front so the list doesn't jump around
+    let height = match (attachment.width, attachment.height) {
+        (Some(width), Some(height)) if width > 0 => Some(IMAGE_WIDTH * height / width),
+        _ => None
+    };
+
+    if let Some(height) = height {
+        picture.set_size_request(IMAGE_WIDTH, height);
+    }
+
+    let placeholder = attachment.blurhash.as_ref().and_then(|hash| {
+        let placeholder_height = height.map(|height| {
+            (BLURHASH_WIDTH * height / IMAGE_WIDTH).max(1)
+        }).unwrap_or(BLURHASH_WIDTH);
+
+        blurhash_pixbuf(hash, BLURHASH_WIDTH, placeholder_height)
+    });
+    picture.set_pixbuf(placeholder.as_ref());
+
+    let path = path.to_owned();
+    MainContext::default().spawn_local(clone!(@weak picture => async move {
+        // Scaled to what's shown on hidpi screens rather than kept full size
+        let pixbuf = async_std::task::spawn_blocking(move || {
+            Pixbuf::from_file_at_scale(&path, IMAGE_WIDTH * 2, -1, true)
+        }).await;
+
+        if let Ok(pixbuf) = pixbuf {
+            picture.set_pixbuf(Some(&pixbuf));
+        }
+    }));
+
+    picture
+}
+
+fn blurhash_pixbuf(hash: &str, width: i32, height: i32) -> Option<Pixbuf> {
+    let pixels = blurhash::decode(hash, width as u32, height as u32)?;
+
+    Some(Pixbuf::from_bytes(
+        &glib::Bytes::from_owned(pixels),
+        Colorspace::Rgb,
+        true,
+        8,
+        width,
+        height,
+        width * 4
+    ))
+}
+
+fn get_video(path: &Path) -> Video {
+    // Video shows its own playback controls
+    Video::builder()
+        .file(&gio::File::for_path(path))
+        .autoplay(false)
+        .height_request(200)
+        .build()
+}
+
+/// Playback controls and how long the clip is, e.g. for voice notes
+fn get_audio(path: &Path) -> Box_ {
+    let media = MediaFile::for_filename(path);
+
+    let duration = Label::builder()
+        .css_classes(vec!["audioDuration".to_owned()])
+        .build();
+
+    // The duration is only known once the file has been loaded
+    media.connect_duration_notify(clone!(@weak duration => move |media| {
+        duration.set_label(&format_duration(media.duration()));
+    }));
+
+    let controls = MediaControls::builder()
+        .media_stream(&media)
+        .hexpand(true)
+        .build();
+
+    let hbox = Box_::new(Orientation::Horizontal, 5);
+    hbox.append(&controls);
+    hbox.append(&duration);
+
+    hbox
+}
+
+/// Shown in place of attachments whose file is gone, e.g. if signald
+/// hasn't downloaded it or its data was deleted
+fn unavailable() -> Box_ {
+    let placeholder = Box_::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(5)
+        .css_classes(vec!["fileAttachment".to_owned()])
+        .build();
+
+    placeholder.append(&Image::from_icon_name(Some("image-missing")));
+    placeholder.append(&Label::new(Some("Media unavailable")));
+
+    placeholder
 }
 
-fn get_picture(attachment: Attachment) -> Picture {
-    Picture::for_filename(format!("run/attachments/{}", attachment.id))
+/// `m:ss` from a media stream's duration in microseconds
+fn format_duration(duration: i64) -> String {
+    let seconds = duration / 1_000_000;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
 }
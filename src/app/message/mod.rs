@@ -3,8 +3,9 @@ mod ui;
 mod url_detect;
 
 use glib::Object;
+use gtk::prelude::*;
 use gtk::glib;
-use crate::models::{NewMessage, Message};
+use signal_rs_core::models::{NewMessage, Message};
 
 glib::wrapper! {
     pub struct MessageObject(ObjectSubclass<imp::MessageObject>);
@@ -32,4 +33,22 @@ impl MessageObject {
             ]
         ).expect("Failed to create MessageObject")
     }
+
+    /// Within a single conversation the timestamp and direction are enough
+    /// to tell messages apart
+    pub fn is_message(&self, timestamp: i64, from_me: bool) -> bool {
+        let own_timestamp = self
+            .property("timestamp")
+            .expect("The property needs to exist and be readable.")
+            .get::<i64>()
+            .expect("The property needs to be of type i64");
+
+        let own_from_me = self
+            .property("from-me")
+            .expect("The property needs to exist and be readable.")
+            .get::<bool>()
+            .expect("The property needs to be of type bool");
+
+        own_timestamp == timestamp && own_from_me == from_me
+    }
 }
@@ -1,20 +1,36 @@
 use super::MessageObject;
 use crate::app::App;
 use gtk::prelude::*;
-use gtk::{Align, Box as Box_, EmojiChooser, GestureClick, GestureLongPress, Justification, Label, Popover};
+use gtk::{Align, Box as Box_, Button, EmojiChooser, EventController, GestureClick, GestureLongPress, Justification,
+          Label, MenuButton, Orientation, Popover, Widget};
 use gtk::glib::{self, clone, MainContext};
-
-use signald::types::{JsonAddressV1, JsonReactionV1,
-                     ReactRequestV1, SignaldTypes};
+use gtk::pango::EllipsizeMode;
 
 use std::rc::Rc;
 
-use crate::database;
-use crate::models::Message;
+use signal_rs_core::database;
+use signal_rs_core::models::{Message, MessageStatus, Reaction};
+
 use crate::app::message::url_detect::find_url;
 
 impl App {
     pub fn message_ui(self: Rc<App>, msg: MessageObject, msg_box: Box_) {
+        // List items get recycled, so clear out whatever was bound before
+        while let Some(child) = msg_box.first_child() {
+            child.unparent();
+        }
+
+        // Along with its gestures and margins, or every rebind would add
+        // another click handler and keep the last sender's side
+        let controllers = msg_box.observe_controllers();
+        while let Some(controller) = controllers.item(0) {
+            let controller = controller.downcast::<EventController>()
+                .expect("The item has to be an EventController");
+            msg_box.remove_controller(&controller);
+        }
+        msg_box.set_margin_start(0);
+        msg_box.set_margin_end(0);
+
         let number = msg
             .property("number")
             .expect("The property needs to exist and be readable.")
@@ -40,7 +56,8 @@ impl App {
             .expect("The property needs to be of type bool");
 
         let msg = database::get_message(
-            &self.db.lock().unwrap(),
+            &self.client.db(),
+            &self.client.account(),
             timestamp,
             number,
             from_me,
@@ -70,6 +87,10 @@ impl App {
             }
         }
 
+        if let (Some(quote_timestamp), Some(quote_author)) = (msg.quote_timestamp, &msg.quote_author) {
+            msg_box.append(&self.clone().quote_ui(quote_timestamp, quote_author, &msg.groupid));
+        }
+
         if let Some(attachments) = &msg.attachments {
             msg_box.append(&self.clone().new_media_viewer(attachments));
         }
@@ -91,6 +112,19 @@ impl App {
 
         msg_box.append(&label);
 
+        let reactions = database::get_reactions(&self.client.db(), &msg);
+        if !reactions.is_empty() {
+            msg_box.append(&self.clone().reaction_chips(&msg, &reactions));
+        }
+
+        if from_me {
+            msg_box.append(&self.clone().status_ui(&msg));
+
+            if let Some(failure) = self.clone().failure_ui(&msg) {
+                msg_box.append(&failure);
+            }
+        }
+
         let right_click = GestureClick::builder()
             .button(3)
             .build();
@@ -102,93 +136,301 @@ impl App {
         msg_box.add_controller(&right_click);
         msg_box.add_controller(&long_touch);
 
-        let reaction = self.clone().get_reaction_menu(&msg_box, msg);
+        let own_reaction = reactions.iter().find(|reaction| {
+            reaction.author == self.client.account()
+        }).map(|reaction| reaction.emoji.clone());
+        let reaction = self.clone().get_reaction_menu(&msg_box, msg.clone(), own_reaction);
+        let menu = self.clone().get_message_menu(&msg_box, msg, &reaction);
 
-        right_click.connect_pressed(clone!(@weak msg_box, @weak reaction => 
+        right_click.connect_pressed(clone!(@weak menu => 
             move|_,_,_,_| {
-                reaction.popup();
+                menu.popup();
             }
         ));
 
-        long_touch.connect_pressed(clone!(@weak msg_box, @weak reaction => 
+        long_touch.connect_pressed(clone!(@weak menu => 
             move|_,_,_| {
-                reaction.popup();
+                menu.popup();
             }
         ));
     }
 
-    fn get_reaction_menu(self: Rc<App>, msg_box: &Box_, msg: Message) -> EmojiChooser {
-        let reaction_selector = EmojiChooser::builder()
-            .position(gtk::PositionType::Bottom)
+    /// The start of the message being replied to, tapping it scrolls back
+    /// to the original
+    fn quote_ui(self: Rc<App>, quote_timestamp: i64, quote_author: &str, groupid: &Option<String>) -> Box_ {
+        let account = self.client.account();
+        let from_me = quote_author == account;
+
+        let author = if from_me {
+            "You".to_owned()
+        } else {
+            self.client.get_name(quote_author).unwrap_or_else(|| quote_author.to_owned())
+        };
+
+        // Quotes of messages from before we were linked can't be shown
+        let body = database::find_authored_message(&self.client.db(), quote_timestamp, quote_author, &account, groupid)
+            .map(|original| original.body)
+            .unwrap_or_else(|| "Original message not found".to_owned());
+
+        let quote = Box_::builder()
+            .orientation(Orientation::Vertical)
+            .css_classes(vec!["quote".to_owned()])
             .build();
 
-        let reactions = Box_::new(gtk::Orientation::Horizontal, 3);
-        let reaction = Popover::builder()
-            .autohide(false)
-            .child(&reactions)
+        quote.append(&Label::builder()
+            .label(&author)
+            .halign(Align::Start)
+            .css_classes(vec!["quoteAuthor".to_owned()])
+            .build());
+
+        quote.append(&Label::builder()
+            .label(&body)
+            .halign(Align::Start)
+            .wrap(true)
+            .lines(3)
+            .ellipsize(EllipsizeMode::End)
+            .build());
+
+        let click = GestureClick::new();
+        click.connect_released(clone!(@strong self as app => move |_,_,_,_| {
+            app.scroll_to_message(quote_timestamp, from_me);
+        }));
+        quote.add_controller(&click);
+
+        quote
+    }
+
+    fn get_message_menu(self: Rc<App>, msg_box: &Box_, msg: Message, reaction: &EmojiChooser) -> Popover {
+        let buttons = Box_::new(Orientation::Horizontal, 3);
+        let menu = Popover::builder()
+            .child(&buttons)
             .has_arrow(false)
             .position(gtk::PositionType::Bottom)
+            .build();
+
+        let reply = Button::with_label("Reply");
+        reply.connect_clicked(clone!(@strong self as app, @weak menu => move |_| {
+            menu.popdown();
+            app.reply_to(msg.clone());
+        }));
+
+        let react = Button::with_label("React");
+        react.connect_clicked(clone!(@weak menu, @weak reaction => move |_| {
+            menu.popdown();
+            reaction.popup();
+        }));
+
+        buttons.append(&reply);
+        buttons.append(&react);
+        menu.set_parent(msg_box);
+
+        menu
+    }
+
+    /// One chip per emoji with how many used it, tapping shows who and
+    /// lets us take back our own
+    fn reaction_chips(self: Rc<App>, msg: &Message, reactions: &[Reaction]) -> Box_ {
+        let chips = Box_::builder()
+            .orientation(Orientation::Horizontal)
+            .spacing(3)
+            .halign(Align::Start)
+            .build();
+
+        // Keep emojis in the order they were first used
+        let mut grouped: Vec<(&str, Vec<&str>)> = Vec::new();
+        for reaction in reactions {
+            match grouped.iter_mut().find(|(grouped_emoji, _)| *grouped_emoji == reaction.emoji.as_str()) {
+                Some((_, authors)) => authors.push(reaction.author.as_str()),
+                None => grouped.push((reaction.emoji.as_str(), vec![reaction.author.as_str()]))
+            }
+        }
+
+        let account = self.client.account();
+        for (emoji, authors) in grouped {
+            let names: Vec<String> = authors.iter().map(|&author| {
+                if author == account {
+                    "You".to_owned()
+                } else {
+                    self.client.get_name(author).unwrap_or_else(|| author.to_owned())
+                }
+            }).collect();
+
+            let who = Label::builder()
+                .label(&names.join("\n"))
+                .build();
+
+            let details = Box_::new(Orientation::Vertical, 5);
+            details.append(&who);
+
+            let popover = Popover::builder()
+                .child(&details)
+                .build();
+
+            if authors.contains(&account.as_str()) {
+                let remove = Button::with_label("Remove");
+                let emoji = emoji.to_owned();
+                remove.connect_clicked(clone!(@strong self as app, @strong msg, @weak popover => move |_| {
+                    popover.popdown();
+                    app.clone().add_reaction(&msg, &emoji, true);
+                }));
+                details.append(&remove);
+            }
+
+            let chip = MenuButton::builder()
+                .label(&format!("{} {}", emoji, authors.len()))
+                .popover(&popover)
+                .css_classes(vec!["reactionChip".to_owned()])
+                .build();
+
+            chips.append(&chip);
+        }
+
+        chips
+    }
+
+    /// Ticks showing how far our message got. In groups tapping them lists
+    /// who has it and who read it.
+    fn status_ui(self: Rc<App>, msg: &Message) -> Widget {
+        let status = msg.status();
+        let (ticks, description) = status_text(status);
+
+        let mut classes = vec!["messageStatus".to_owned()];
+        match status {
+            MessageStatus::Read => classes.push("read".to_owned()),
+            MessageStatus::Failed => classes.push("failed".to_owned()),
+            _ => {}
+        }
+
+        if msg.groupid.is_none() {
+            return Label::builder()
+                .label(ticks)
+                .tooltip_text(description)
+                .halign(Align::End)
+                .css_classes(classes)
+                .build()
+                .upcast();
+        }
+
+        let details = Box_::new(Orientation::Vertical, 5);
+        let receipts = database::get_receipts(&self.client.db(), msg);
+        if receipts.is_empty() {
+            details.append(&Label::new(Some(description)));
+        }
+
+        for receipt in receipts {
+            let name = self.client.get_name(&receipt.member).unwrap_or_else(|| receipt.member.clone());
+            let (_, description) = status_text(receipt.status());
+            let when = glib::DateTime::from_unix_local(receipt.timestamp / 1000)
+                .and_then(|when| when.format("%b %e, %H:%M"))
+                .map(|when| format!(" {}", when))
+                .unwrap_or_default();
+
+            details.append(&Label::builder()
+                .label(&format!("{}: {}{}", name, description, when))
+                .halign(Align::Start)
+                .build());
+        }
+
+        MenuButton::builder()
+            .label(ticks)
+            .tooltip_text(description)
             .halign(Align::End)
+            .css_classes(classes)
+            .popover(&Popover::builder().child(&details).build())
+            .build()
+            .upcast()
+    }
+
+    /// Who our message didn't reach, with a way to try again
+    fn failure_ui(self: Rc<App>, msg: &Message) -> Option<Box_> {
+        let missed: Vec<String> = database::get_receipts(&self.client.db(), msg).iter()
+            .filter(|receipt| receipt.status() == MessageStatus::Failed)
+            .map(|receipt| self.client.get_name(&receipt.member).unwrap_or_else(|| receipt.member.clone()))
+            .collect();
+
+        let text = if msg.status() == MessageStatus::Failed {
+            "Not sent".to_owned()
+        } else if !missed.is_empty() {
+            format!("Not sent to {}", missed.join(", "))
+        } else {
+            return None;
+        };
+
+        let row = Box_::builder()
+            .orientation(Orientation::Horizontal)
+            .spacing(5)
+            .halign(Align::End)
+            .css_classes(vec!["sendFailure".to_owned()])
             .build();
 
-        reaction.set_parent(msg_box);
+        row.append(&Label::builder()
+            .label(&text)
+            .wrap(true)
+            .build());
+
+        if self.client.can_retry(msg) {
+            let retry = Button::with_label("Retry");
+            retry.connect_clicked(clone!(@strong self as app, @strong msg => move |retry| {
+                retry.set_sensitive(false);
+                app.clone().retry_message(msg.clone());
+            }));
+            row.append(&retry);
+        }
+
+        Some(row)
+    }
+
+    fn retry_message(self: Rc<App>, msg: Message) {
+        MainContext::default().spawn_local(clone!(@strong self as app => async move {
+            if let Err(e) = app.client.retry(&msg).await {
+                app.show_error(&e);
+            }
+
+            app.refresh_message(msg.timestamp, &msg.number, true, &msg.groupid);
+        }));
+    }
 
-        reaction_selector.connect_emoji_picked(clone!(@strong self as app, @weak reactions, @weak reaction, @weak msg_box =>
-            move |selector, emoji| {
-                let label = Label::new(Some(emoji));
-                reactions.append(&label);
-                reaction.popup();
+    /// Picking our current reaction again takes it back, anything else
+    /// replaces it
+    fn get_reaction_menu(self: Rc<App>, msg_box: &Box_, msg: Message, own_reaction: Option<String>) -> EmojiChooser {
+        let reaction_selector = EmojiChooser::builder()
+            .position(gtk::PositionType::Bottom)
+            .build();
 
-                selector.popdown();
-                msg_box.set_margin_bottom(35);
+        reaction_selector.connect_emoji_picked(clone!(@strong self as app => move |selector, emoji| {
+            selector.popdown();
 
-                app.clone().add_reaction(&msg, emoji);
+            match &own_reaction {
+                Some(own) if own == emoji => app.clone().add_reaction(&msg, emoji, true),
+                _ => app.clone().add_reaction(&msg, emoji, false)
             }
-        ));
+        }));
 
         reaction_selector.set_parent(msg_box);
 
         reaction_selector
     }
 
-    fn add_reaction(self: Rc<App>, msg: &Message, emoji: &str) {
-        let reaction_request = create_react_request(msg, &self.account.borrow(), emoji);
+    /// Send the reaction and redraw the message once signald has it
+    fn add_reaction(self: Rc<App>, msg: &Message, emoji: &str, remove: bool) {
+        let emoji = emoji.to_owned();
+        let msg = msg.clone();
         MainContext::default().spawn_local(clone!(@strong self as app => async move {
-            app.clone().dispatch(
-                "react",
-                SignaldTypes::ReactRequestV1(
-                    reaction_request
-                )
-            ).await;
+            match app.client.react(&msg, &emoji, remove).await {
+                Ok(()) => app.refresh_message(msg.timestamp, &msg.number, msg.from_me, &msg.groupid),
+                Err(e) => app.show_error(&e)
+            }
         }));
     }
 }
 
-fn create_react_request(msg: &Message, account: &String, emoji: &str) -> ReactRequestV1 {
-    ReactRequestV1 {
-        reaction: Some(JsonReactionV1 {
-            emoji: Some(emoji.to_owned()),
-            remove: Some(false),
-            target_author: Some(JsonAddressV1 {
-                number: msg.number.clone().or(
-                    Some(account.clone())
-                ),
-                uuid: None,
-                relay: None
-            }),
-            target_sent_timestamp: Some(msg.timestamp)
-        }),
-        recipient_address: msg.number.as_ref().map(|number| {
-            JsonAddressV1 {
-                number: Some(number.clone()),
-                uuid: None,
-                relay: None
-            }
-        }),
-        recipient_group_id: msg.groupid.clone(),
-        timestamp: Some(chrono::offset::Local::now().timestamp_millis()),
-        username: Some(account.clone())
-
+fn status_text(status: MessageStatus) -> (&'static str, &'static str) {
+    match status {
+        MessageStatus::Failed => ("!", "Not sent"),
+        MessageStatus::Sending => ("…", "Sending"),
+        MessageStatus::Sent => ("✓", "Sent"),
+        MessageStatus::Delivered => ("✓✓", "Delivered"),
+        MessageStatus::Read => ("✓✓", "Read")
     }
 }
 
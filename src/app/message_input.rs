@@ -1,152 +1,378 @@
 use gtk::prelude::*;
-use gtk::{Entry, Box as Box_, Button, EventControllerFocus, Orientation};
+use gtk::{Align, ApplicationWindow, Entry, Box as Box_, Button, FileChooserAction,
+          FileChooserNative, Image, Label, Orientation, Picture, ResponseType, Revealer, Widget};
+use gtk::gio;
 use gtk::glib::{self, clone, MainContext};
+use gtk::pango::EllipsizeMode;
 
 use std::rc::Rc;
-use std::sync::{Arc, Mutex};
-use chrono;
-use diesel::sqlite::SqliteConnection;
+use std::cell::{Cell, RefCell};
+use std::time::{Duration, Instant};
 
-use signald::types::{TypingRequestV1, SendRequestV1, SignaldTypes};
+use signal_rs_core::OutgoingAttachment;
+use signal_rs_core::conversation::Conversation;
+use signal_rs_core::models::Message;
 
-use crate::models::NewMessage;
-use crate::app::conversation::{Conversation, ConversationType};
 use crate::app::App;
-use crate::database;
-use crate::app::MessageObject;
-use crate::signal_type_utils::*;
 
-impl App {
-    pub fn message_input_ui(self: Rc<App>, conversation: Rc<Conversation>) -> Box_ {
+/// The bar above the entry showing which message is being replied to
+pub struct ReplyPreview {
+    revealer: Revealer,
+    author: Label,
+    body: Label,
+    message: RefCell<Option<Message>>
+}
+
+impl ReplyPreview {
+    pub fn new() -> Rc<Self> {
+        let author = Label::builder()
+            .halign(Align::Start)
+            .css_classes(vec!["quoteAuthor".to_owned()])
+            .build();
+
+        let body = Label::builder()
+            .halign(Align::Start)
+            .ellipsize(EllipsizeMode::End)
+            .single_line_mode(true)
+            .build();
+
+        let text = Box_::builder()
+            .orientation(Orientation::Vertical)
+            .hexpand(true)
+            .build();
+        text.append(&author);
+        text.append(&body);
+
+        let cancel = Button::builder()
+            .icon_name("window-close")
+            .valign(Align::Center)
+            .build();
+
         let hbox = Box_::builder()
             .orientation(Orientation::Horizontal)
+            .css_classes(vec!["replyPreview".to_owned()])
             .build();
+        hbox.append(&text);
+        hbox.append(&cancel);
 
-        let msg_entry = Entry::builder()
-            .hexpand(true)
-            .show_emoji_icon(true)
+        let revealer = Revealer::builder()
+            .child(&hbox)
+            .reveal_child(false)
             .build();
 
-        let focus_controller = EventControllerFocus::new();
-        focus_controller.connect_enter(clone!(@strong self as app, @strong conversation => move |_| {
-            let typing = construct_typing(&app.account.borrow(), conversation.clone(), true);
-            MainContext::default().spawn_local(clone!(@strong app => async move {
-                app.clone().dispatch(
-                    "typing",
-                    SignaldTypes::TypingRequestV1(
-                        typing
-                    )
-                ).await;
-            }));
+        let preview = Rc::new(ReplyPreview {
+            revealer,
+            author,
+            body,
+            message: RefCell::new(None)
+        });
+
+        cancel.connect_clicked(clone!(@weak preview => move |_| {
+            preview.clear();
         }));
 
-        focus_controller.connect_leave(clone!(@strong self as app, @strong conversation => move |_| {
-            let typing = construct_typing(&app.account.borrow(), conversation.clone(), false);
-            MainContext::default().spawn_local(clone!(@strong app => async move {
-                app.clone().dispatch(
-                    "typing",
-                    SignaldTypes::TypingRequestV1(
-                        typing
-                    )
-                ).await;
-            }));
+        preview
+    }
+
+    pub fn show(&self, author: &str, msg: Message) {
+        self.author.set_label(author);
+        self.body.set_label(&msg.body);
+        self.message.replace(Some(msg));
+        self.revealer.set_reveal_child(true);
+    }
+
+    pub fn clear(&self) {
+        self.message.replace(None);
+        self.revealer.set_reveal_child(false);
+    }
+
+    /// The message being replied to
+    fn message(&self) -> Option<Message> {
+        self.message.borrow().clone()
+    }
+}
+
+/// Files picked to go out with the next message, shown as removable
+/// thumbnails above the entry
+pub struct PendingAttachments {
+    row: Box_,
+    files: RefCell<Vec<(OutgoingAttachment, Box_)>>,
+    // Native dialogs aren't kept alive by GTK while they're open
+    chooser: RefCell<Option<FileChooserNative>>
+}
+
+impl PendingAttachments {
+    fn new() -> Rc<Self> {
+        let row = Box_::builder()
+            .orientation(Orientation::Horizontal)
+            .spacing(5)
+            .visible(false)
+            .build();
+
+        Rc::new(PendingAttachments {
+            row,
+            files: RefCell::new(Vec::new()),
+            chooser: RefCell::new(None)
+        })
+    }
+
+    fn choose(self: Rc<Self>, window: &ApplicationWindow) {
+        let chooser = FileChooserNative::new(
+            Some("Attach files"),
+            Some(window),
+            FileChooserAction::Open,
+            Some("Attach"),
+            Some("Cancel")
+        );
+        chooser.set_select_multiple(true);
+
+        chooser.connect_response(clone!(@strong self as pending => move |chooser, response| {
+            if response == ResponseType::Accept {
+                let files = chooser.files();
+                for position in 0..files.n_items() {
+                    let file = files.item(position)
+                        .expect("The item has to exist.")
+                        .downcast::<gio::File>()
+                        .expect("The item has to be a File");
+
+                    pending.clone().add(&file);
+                }
+            }
+
+            pending.chooser.replace(None);
         }));
 
-        msg_entry.add_controller(&focus_controller);
+        chooser.show();
+        self.chooser.replace(Some(chooser));
+    }
+
+    fn add(self: Rc<Self>, file: &gio::File) {
+        let path = match file.path().and_then(|path| path.to_str().map(|path| path.to_owned())) {
+            Some(path) => path,
+            None => return
+        };
+
+        let content_type = file.query_info("standard::content-type", gio::FileQueryInfoFlags::NONE, gio::NONE_CANCELLABLE)
+            .ok()
+            .and_then(|info| info.content_type())
+            .and_then(|content_type| gio::content_type_get_mime_type(&content_type))
+            .map(|mime_type| mime_type.to_string())
+            .unwrap_or_else(|| "application/octet-stream".to_owned());
+
+        let tile = Box_::builder()
+            .orientation(Orientation::Vertical)
+            .css_classes(vec!["attachmentThumbnail".to_owned()])
+            .build();
+
+        let remove = Button::builder()
+            .icon_name("window-close")
+            .halign(Align::End)
+            .build();
+
+        remove.connect_clicked(clone!(@weak self as pending, @weak tile => move |_| {
+            pending.files.borrow_mut().retain(|(_, other)| *other != tile);
+            pending.row.remove(&tile);
+            pending.row.set_visible(!pending.files.borrow().is_empty());
+        }));
+
+        tile.append(&remove);
+        tile.append(&thumbnail(file, &path, &content_type));
+
+        self.row.append(&tile);
+        self.row.set_visible(true);
+        self.files.borrow_mut().push((OutgoingAttachment { path, content_type }, tile));
+    }
+
+    /// The picked files
+    fn picked(&self) -> Vec<OutgoingAttachment> {
+        self.files.borrow().iter().map(|(attachment, _)| attachment.clone()).collect()
+    }
+
+    /// Forget the picked files, clearing the thumbnails
+    fn clear(&self) {
+        for (_, tile) in self.files.borrow_mut().drain(..) {
+            self.row.remove(&tile);
+        }
+        self.row.set_visible(false);
+    }
+}
+
+// Signal clients drop a typing indicator after 15 seconds without a refresh
+const TYPING_REFRESH: Duration = Duration::from_secs(10);
+// How long after the last keystroke we count as having stopped
+const TYPING_IDLE: Duration = Duration::from_secs(5);
+
+/// Sends typing started while the entry is being edited, refreshing it
+/// periodically, and stopped once idle, cleared or sent
+struct TypingSender {
+    app: Rc<App>,
+    conversation: Rc<Conversation>,
+    // When we last told the conversation we're typing, if we still are
+    started: Cell<Option<Instant>>,
+    // Bumped on every keystroke so stale idle timers do nothing
+    generation: Cell<u64>
+}
+
+impl TypingSender {
+    fn new(app: Rc<App>, conversation: Rc<Conversation>) -> Rc<Self> {
+        Rc::new(TypingSender {
+            app,
+            conversation,
+            started: Cell::new(None),
+            generation: Cell::new(0)
+        })
+    }
+
+    fn changed(self: Rc<Self>, text: &str) {
+        if text.is_empty() {
+            self.stop();
+            return;
+        }
+
+        let refresh = self.started.get()
+            .map(|started| started.elapsed() >= TYPING_REFRESH)
+            .unwrap_or(true);
+        if refresh {
+            self.started.set(Some(Instant::now()));
+            self.send(true);
+        }
+
+        let generation = self.generation.get() + 1;
+        self.generation.set(generation);
+        glib::timeout_add_local_once(TYPING_IDLE, clone!(@weak self as typing => move || {
+            if typing.generation.get() == generation {
+                typing.stop();
+            }
+        }));
+    }
+
+    fn stop(&self) {
+        if self.started.take().is_some() {
+            self.send(false);
+        }
+    }
+
+    fn send(&self, typing: bool) {
+        let app = self.app.clone();
+        let conversation = self.conversation.clone();
+        MainContext::default().spawn_local(async move {
+            if let Err(e) = app.client.typing(&conversation, typing).await {
+                app.show_error(&e);
+            }
+        });
+    }
+}
+
+impl App {
+    pub fn message_input_ui(self: Rc<App>, conversation: Rc<Conversation>, reply: Rc<ReplyPreview>, msg_entry: Entry) -> Box_ {
+        let vbox = Box_::builder()
+            .orientation(Orientation::Vertical)
+            .build();
+
+        let hbox = Box_::builder()
+            .orientation(Orientation::Horizontal)
+            .build();
+
+        let typing = TypingSender::new(self.clone(), conversation.clone());
+        msg_entry.connect_changed(clone!(@strong typing => move |entry| {
+            typing.clone().changed(&entry.text());
+        }));
+
+        let attachments = PendingAttachments::new();
+
+        let attach_button = Button::builder()
+            .icon_name("mail-attachment")
+            .build();
+
+        attach_button.connect_clicked(clone!(@strong self as app, @strong attachments => move |_| {
+            attachments.clone().choose(&app.window);
+        }));
 
         let send_button = Button::builder()
             .icon_name("mail-send")
             .build();
 
         let app = self.clone();
-        send_button.connect_clicked(clone!(@weak msg_entry, @strong conversation, @strong app =>
+        send_button.connect_clicked(clone!(@weak msg_entry, @strong conversation, @strong app, @strong reply, @strong attachments, @strong typing =>
             move |_| {
                 let main_context = MainContext::default();
                 let msg_body = msg_entry.text().to_string();
+                let picked = attachments.picked();
+
+                // Nothing to send
+                if msg_body.trim().is_empty() && picked.is_empty() {
+                    return;
+                }
+
+                // Keep what was typed and picked if it can't go out
+                let msg = match app.client.compose(&conversation, msg_body, reply.message().as_ref(), &picked) {
+                    Ok(msg) => msg,
+                    Err(e) => {
+                        app.show_error(&e);
+                        return;
+                    }
+                };
+
                 msg_entry.delete_text(0, -1);
+                typing.stop();
+                reply.clear();
+                attachments.clear();
 
-                let msg = construct_message(&app.account.borrow(), conversation.clone(), msg_body);
-                store_message(app.db.clone(), &msg, conversation.clone());
+                let stored = app.client.store_outgoing(&msg);
+                app.append_to_active(&stored);
 
                 main_context.spawn_local(clone!(@weak msg_entry, @strong app =>
                     async move {
-                        app.dispatch(
-                            "send",
-                            SignaldTypes::SendRequestV1(msg)
-                        ).await;
+                        if let Err(e) = app.client.send(msg).await {
+                            app.show_error(&e);
+                        }
+
+                        // Now sent or failed
+                        app.refresh_message(stored.timestamp, &stored.number, true, &stored.groupid);
                     }
                 ));
             }
         ));
 
+        hbox.append(&attach_button);
         hbox.append(&msg_entry);
         hbox.append(&send_button);
-        hbox
-    }
-}
 
-fn construct_typing(username: &String, conversation: Rc<Conversation>, typing: bool) -> TypingRequestV1 {
-    TypingRequestV1 {
-        account: Some(username.clone()),
-        address: match &conversation.conversation_type {
-            ConversationType::Individual(conv) => conv.address.clone(),
-            ConversationType::Group(_) => None
-        },
-        group: match &conversation.conversation_type {
-            ConversationType::Group(group) => group.id.clone(),
-            ConversationType::Individual(_) => None
-        },
-        typing: Some(typing),
-        when: Some(chrono::offset::Local::now().timestamp_millis())
+        vbox.append(&reply.revealer);
+        vbox.append(&attachments.row);
+        vbox.append(&hbox);
+        vbox
     }
 }
 
-fn construct_message(username: &String, conversation: Rc<Conversation>, body: String) -> SendRequestV1 {
-    SendRequestV1 {
-        username: Some(username.clone()),
-        recipient_address: match &conversation.conversation_type {
-            ConversationType::Individual(conv) => conv.address.clone(),
-            ConversationType::Group(_) => None
-        },
-        recipient_group_id: match &conversation.conversation_type {
-            ConversationType::Group(group) => group.id.clone(),
-            ConversationType::Individual(_) => None
-        },
-        message_body: Some(body),
-        attachments: None,
-        quote: None,
-        timestamp: Some(chrono::offset::Local::now().timestamp_millis()),
-        mentions: None
+// A preview of images, otherwise the file type's icon and name
+fn thumbnail(file: &gio::File, path: &str, content_type: &str) -> Widget {
+    if content_type.starts_with("image/") {
+        return Picture::builder()
+            .file(file)
+            .can_shrink(true)
+            .width_request(64)
+            .height_request(64)
+            .build()
+            .upcast();
     }
-}
 
-fn store_message(db: Arc<Mutex<SqliteConnection>>, msg: &SendRequestV1, conversation: Rc<Conversation>) {
-    let (mentions, mentions_start) = database::convert_mentions(&msg.mentions);
-    let msg = NewMessage {
-        timestamp: msg.timestamp.unwrap(),
-        number: msg.recipient_address.as_ref().map(|address| {
-            address.number.unwrap_clone()
-        }),
-        from_me: true,
-        is_read: false,
-        attachments: database::store_attachments(&db.lock().unwrap(), msg.attachments.as_ref()),
-        body: msg.message_body.unwrap_clone(),
-        groupid: msg.recipient_group_id.as_ref().map(|id| id.clone()),
-        quote_timestamp: msg.quote.as_ref().map(|quote| quote.id.unwrap()),
-        quote_author: msg.quote.as_ref().map(|quote| {
-            quote.author.get_number()
-        }),
-        mentions,
-        mentions_start,
-        reaction_emojis: None,
-        reaction_authors: None
-    };
-
-    database::store_message(&db.lock().unwrap(), &msg);
-
-    conversation.model
-        .borrow_mut()
-        .as_ref()
-        .unwrap()
-        .append(&MessageObject::new_sent(&msg));
+    let tile = Box_::new(Orientation::Vertical, 3);
+    tile.append(&Image::builder()
+        .gicon(&gio::content_type_get_icon(content_type))
+        .pixel_size(48)
+        .build());
+
+    let name = std::path::Path::new(path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(path);
+
+    tile.append(&Label::builder()
+        .label(name)
+        .ellipsize(EllipsizeMode::Middle)
+        .max_width_chars(12)
+        .build());
+
+    tile.upcast()
 }
@@ -1,264 +1,298 @@
 use std::rc::Rc;
-use std::sync::{Arc, Mutex};
 use std::cell::RefCell;
-use std::collections::HashMap;
-use signald::types::{SignaldTypes, ListAccountsRequestV1, ListContactsRequestV1,
-                     ListGroupsRequestV1, ProfileV1, RequestSyncRequestV1, 
-                     SubscribeRequestV1};
-use diesel::sqlite::SqliteConnection;
+use std::time::Duration;
 
 use gtk::prelude::*;
-use gtk::{Application, ApplicationWindow, Widget};
-use gtk::glib::{clone, MainContext};
+use gtk::{Align, Application, ApplicationWindow, Label, Overlay, Widget};
+use gtk::glib::{self, clone, MainContext};
 
-use async_std::channel::{bounded, Sender, Receiver};
+use async_std::channel::bounded;
 
-use crate::signald_bridge::{listen, SignaldInteraction};
-use crate::database::establish_connection;
-use crate::models::NewMessage;
-use crate::signal_type_utils::*;
+use signal_rs_core::{Client, Error, Event, Result};
+use signal_rs_core::conversation::{Conversation, TYPING_TIMEOUT};
+use signal_rs_core::models::NewMessage;
+use signal_rs_core::notifications::ConnectionState;
 
+mod background;
 pub mod link_device;
+mod linked_devices;
 pub mod load_app;
 pub mod main_view;
 pub mod conversation;
+mod gallery;
 pub mod message;
-pub mod notifications;
 pub mod message_input;
 mod media_viewer;
+mod notify;
 
-use notifications::Notification;
+use conversation::ActiveConversation;
 use message::MessageObject;
 
-type ContactMap = HashMap<String, ProfileV1>;
+const ERROR_DISPLAY_TIME: Duration = Duration::from_secs(5);
 
 pub struct App {
-    account: RefCell<String>,
+    client: Rc<Client>,
+    application: Application,
     window: ApplicationWindow,
-    signald_sender: Sender<SignaldInteraction>,
-    notification_receiver: Receiver<Notification>,
-    conversations: RefCell<Vec<Rc<conversation::Conversation>>>,
+    overlay: Overlay,
+    status: Label,
     curr_view: RefCell<&'static str>,
-    contacts: RefCell<ContactMap>,
-    db: Arc<Mutex<SqliteConnection>>
+    active: RefCell<Option<ActiveConversation>>
 }
 
 impl App {
     pub fn new(application: &Application) -> Rc<Self> {
-        let (msg_sender, msg_receiver) = bounded(10);
-        let (notification_sender, notification_receiver) = bounded(10);
         let main_context = MainContext::default();
-        let db = Arc::new(Mutex::new(establish_connection()));
 
-        main_context.spawn_local(clone!(@strong db => async move {
-            listen(db, msg_receiver, notification_sender).await;
-        }));
+        let window = ApplicationWindow::new(application);
+        let overlay = Overlay::new();
+        let status = Label::builder()
+            .css_classes(vec!["connectionStatus".to_owned()])
+            .halign(Align::Center)
+            .valign(Align::Start)
+            .visible(false)
+            .build();
+
+        overlay.add_overlay(&status);
+        window.set_child(Some(&overlay));
 
         let app = Rc::new(App {
-            account: RefCell::new(String::new()),
-            window: ApplicationWindow::new(application),
-            signald_sender: msg_sender,
-            notification_receiver, 
-            conversations: RefCell::new(Vec::new()),
+            client: Client::from_env(),
+            application: application.clone(),
+            window,
+            overlay,
+            status,
             curr_view: RefCell::new("none"),
-            contacts: RefCell::new(HashMap::new()),
-            db
+            active: RefCell::new(None)
         });
 
         application.connect_activate(clone!(@strong app => move |_| {
             app.window.show()
         }));
 
+        app.clone().add_notification_actions();
+        app.clone().setup_background();
+
         main_context.spawn_local(clone!(@strong app => async move {
             app.initialize().await;
         }));
 
+        // Handled separately from initialize so connection state is shown
+        // while the first signald calls are still pending
+        main_context.spawn_local(clone!(@strong app => async move {
+            app.handle_notifications().await;
+        }));
+
         app
     }
 
     pub async fn initialize(self: Rc<App>) {
-        let account_list = self.clone().dispatch(
-            "list_accounts",
-            SignaldTypes::ListAccountsRequestV1(
-                ListAccountsRequestV1::default()
-            )
-        ).await;
-
-        if let SignaldTypes::AccountListV1(account_list)  = account_list {
-            let mut accounts = account_list.accounts.unwrap();
-
-            // Assume at most one account is returned, because that's
-            // all that's supported as of now
-            let account = match accounts.pop() {
-                Some(account) => account.account_id.unwrap(),
-                None => {
-                    let (sender, receiver) = bounded(1);
-                    self.update_ui(
-                        &link_device::build_ui(self.clone(), sender),
-                        "new_device"
-                    );
-                    receiver.recv().await.expect("Can't get account number")
-                }
-            };
+        self.update_ui(&main_view::loading(), "loading");
+
+        if let Err(e) = self.clone().load_account().await {
+            self.update_ui(&load_app::load_error_ui(self.clone(), &e), "load_error");
+        }
+    }
 
-            *self.account.borrow_mut() = account.clone();
+    async fn load_account(self: Rc<App>) -> Result<()> {
+        let accounts = self.client.load_accounts().await?;
+
+        // Start where we left off, or with the newest account
+        let account = match self.client.last_account().or_else(|| accounts.last().cloned()) {
+            Some(account) => account,
+            None => {
+                let (sender, receiver) = bounded(1);
+                self.update_ui(
+                    &link_device::build_ui(self.clone(), sender),
+                    "new_device"
+                );
+                receiver.recv().await.expect("Can't get account number")
+            }
+        };
 
-            *self.conversations.borrow_mut() = self.clone()
-                .get_conversations(&account).await;
+        self.client.load_account(account).await?;
+        self.update_ui(&self.clone().main_view_ui(), "main_view");
 
-            self.clone().order_conversations();
-            self.update_ui(&self.clone().main_view_ui(), "main_view");
+        self.client.subscribe().await?;
+        self.flush_outbox().await;
 
-            self.clone().dispatch(
-                "subscribe",
-                SignaldTypes::SubscribeRequestV1(
-                    SubscribeRequestV1 {
-                        account: Some(account.clone())
-                    }
-                )
-            ).await;
-
-            self.clone().dispatch(
-                "request_sync",
-                SignaldTypes::RequestSyncRequestV1(
-                    RequestSyncRequestV1 {
-                        account: Some(account),
-                        groups: Some(true),
-                        configuration: Some(true),
-                        contacts: Some(true),
-                        blocked: Some(true)
-                    }
-                )
-            ).await;
-        } else {
-            panic!("Incorrect return type from list_accounts call");
+        Ok(())
+    }
+
+    /// Show the conversations of `account` instead of the active one
+    pub async fn switch_account(self: Rc<App>, account: String) {
+        self.active.replace(None);
+        self.update_ui(&main_view::loading(), "loading");
+
+        if let Err(e) = self.client.load_account(account).await {
+            self.show_error(&e);
         }
+        self.update_ui(&self.clone().main_view_ui(), "main_view");
+    }
+
+    /// Link or register another account and switch to it. Going back
+    /// from the welcome screen closes the channel, which gives up.
+    pub async fn add_account(self: Rc<App>) {
+        let (sender, receiver) = bounded(1);
+        self.update_ui(
+            &link_device::build_ui(self.clone(), sender),
+            "new_device"
+        );
+
+        let account = match receiver.recv().await {
+            Ok(account) => account,
+            Err(_) => return
+        };
 
-        self.handle_notifications().await;
+        self.clone().switch_account(account.clone()).await;
+        if let Err(e) = self.client.subscribe_account(&account).await {
+            self.show_error(&e);
+        }
+    }
+
+    /// Send whatever queued up while signald was unreachable
+    async fn flush_outbox(&self) {
+        for msg in self.client.flush_outbox().await.iter().filter(|msg| msg.account == self.client.account()) {
+            self.refresh_message(msg.timestamp, &msg.number, true, &msg.groupid);
+        }
     }
 
     async fn handle_notifications(self: Rc<App>) {
         loop {
-            let notification = self.clone()
-                .notification_receiver.recv()
-                .await
-                .expect("Failed to receive notification");
-            
-            match notification {
-                Notification::NewMessage(msg) => {
-                    self.clone().message_notification(msg).await;
+            match self.client.next_event().await {
+                Event::NewMessage { conversation, message } => {
+                    self.clone().message_notification(conversation, message);
                 },
-                Notification::Reaction(_reaction) => {
+                Event::Reaction(reaction) => {
+                    self.refresh_message(
+                        reaction.message_timestamp,
+                        &reaction.message_number,
+                        reaction.message_from_me,
+                        &reaction.groupid
+                    );
+                },
+                Event::Receipt(messages) => {
+                    for msg in messages {
+                        self.refresh_message(msg.timestamp, &msg.number, true, &msg.groupid);
+                    }
+                },
+                Event::Read { conversations } => {
+                    self.clone().read_notification(conversations);
+                },
+                Event::Typing { conversation } => {
+                    self.clone().typing_notification(conversation);
+                },
+                Event::Connection(state) => {
+                    self.clone().connection_notification(state);
                 }
             }
         }
     }
 
-    async fn message_notification(self: Rc<App>, msg: NewMessage) {
-        self.clone().order_conversations();
-        (*self.conversations.borrow())[0].notify_msg(msg);
+    fn message_notification(self: Rc<App>, conversation: Option<Rc<Conversation>>, msg: NewMessage) {
+        self.append_to_active(&msg);
+
+        match conversation {
+            Some(conversation) => self.clone().notify(&conversation, &msg),
+            None if msg.account != self.client.account() => self.clone().notify_other_account(&msg),
+            None => {}
+        }
 
         // Redraw main view after adding notification
         if self.curr_view.borrow().eq("main_view") {
             self.clone().update_ui(&self.clone().main_view_ui(), "main_view");
         }
-
     }
 
-    fn order_conversations(self: Rc<App>) {
-        self.conversations.borrow().iter().for_each(|conv| {
-            conv.set_last_message(&self.db.lock().unwrap());
-        });
+    // Read on another device, so clear the badges and notifications here
+    fn read_notification(self: Rc<App>, conversations: Vec<Rc<Conversation>>) {
+        for conversation in &conversations {
+            if *conversation.new_msgs.borrow() == 0 {
+                self.withdraw_notification(conversation);
+            }
+        }
 
-        self.conversations.borrow_mut().sort_by(|c1, c2| {
-            c2.last_message_time.borrow().cmp(
-                &c1.last_message_time.borrow()
-            )
-        });
+        if self.curr_view.borrow().eq("main_view") {
+            self.clone().update_ui(&self.clone().main_view_ui(), "main_view");
+        }
     }
 
-    pub async fn dispatch(self: Rc<App>, key: &'static str, msg: SignaldTypes) -> SignaldTypes {
-        let (sender, receiver) = bounded(1);
-
-        self.signald_sender.send(
-            SignaldInteraction {
-                key,
-                msg,
-                response_channel: Some(sender)
-            }
-        ).await.expect("Can't interact with signald bridge");
+    fn typing_notification(self: Rc<App>, conversation: Option<Rc<Conversation>>) {
+        if conversation.is_none() {
+            return;
+        }
 
-        receiver.recv().await
-            .expect("Couldn't receive signald response").msg
-    }
+        self.redraw_typing();
 
-    pub fn update_ui<P: IsA<Widget>>(&self, child: &P, view: &'static str) {
-        self.curr_view.replace(view);
-        self.window.set_child(Some(child));
+        // Nobody says when they stop typing if they just go quiet
+        glib::timeout_add_local_once(TYPING_TIMEOUT, clone!(@strong self as app => move || {
+            app.redraw_typing();
+        }));
     }
 
-    async fn get_conversations(self: Rc<App>, account: &String) -> Vec<Rc<conversation::Conversation>> {
-        let contacts = self.clone().dispatch(
-            "list_contacts",
-            SignaldTypes::ListContactsRequestV1(
-                ListContactsRequestV1 {
-                    account: Some(account.clone()),
-                    async_: Some(true)
-                }
-            )
-        ).await;
+    fn redraw_typing(self: Rc<App>) {
+        self.refresh_typing();
 
-        let mut conversations = self.clone().get_profiles(
-            contacts,
-            &mut *self.contacts.borrow_mut()
-        );
+        if self.curr_view.borrow().eq("main_view") {
+            self.update_ui(&self.clone().main_view_ui(), "main_view");
+        }
+    }
 
-        let groups = self.clone().dispatch(
-            "list_groups",
-            SignaldTypes::ListGroupsRequestV1(
-                ListGroupsRequestV1 {
-                    account: Some(account.clone()),
+    fn connection_notification(self: Rc<App>, state: ConnectionState) {
+        match state {
+            ConnectionState::Connecting => self.set_status(Some("Connecting to signald…")),
+            ConnectionState::Connected => {
+                self.set_status(None);
+
+                // Before an account is loaded there's nothing to send yet
+                if !self.client.account().is_empty() {
+                    MainContext::default().spawn_local(clone!(@strong self as app => async move {
+                        app.flush_outbox().await;
+                    }));
                 }
-            )
-        ).await;
+            },
+            ConnectionState::Reconnecting { attempt, retry_in } => self.set_status(Some(
+                &format!("Reconnecting… (attempt {}, retrying in {:.1}s)", attempt, retry_in.as_secs_f32())
+            ))
+        }
+    }
 
-        conversations.append(&mut self.get_groups(groups));
+    /// Show a banner across the top of the window, or hide it with `None`
+    pub fn set_status(&self, status: Option<&str>) {
+        match status {
+            Some(text) => {
+                self.status.set_label(text);
+                self.status.show();
+            },
+            None => self.status.hide()
+        }
+    }
 
-        conversations
+    /// Show an error in the status banner for a few seconds
+    pub fn show_error(&self, error: &Error) {
+        self.flash_status(&error.to_string());
     }
 
-    pub fn get_name(self: Rc<App>, number: &str) -> Option<String> {
-        (*self.contacts.borrow()).get(number).as_ref().map(|profile| {
-            profile.get_name()
-        })
+    /// Show a banner for a few seconds
+    pub fn flash_status(&self, text: &str) {
+        let text = text.to_owned();
+        let status = self.status.clone();
+        self.set_status(Some(&text));
+
+        glib::timeout_add_local_once(ERROR_DISPLAY_TIME, move || {
+            // Don't hide a banner that has since been replaced
+            if status.label().as_str() == text {
+                status.hide();
+            }
+        });
     }
 
-    fn get_profiles(self: Rc<App>, contacts: SignaldTypes, profiles: &mut ContactMap) -> Vec<Rc<conversation::Conversation>> {
-        if let SignaldTypes::ProfileListV1(profile_list) = contacts {
-            profile_list.profiles.unwrap().drain(..).filter_map(|profile| {
-                let number = profile.address.get_number();
-                profiles.insert(number, profile.clone());
-
-                let db = self.db.lock().unwrap();
-                conversation::Conversation::new_individual(profile, &db).map(|conv| {
-                    Rc::new(conv)
-                })
-            }).collect()
-        } else {
-            panic!("Wrong type");
-        }
+    pub fn update_ui<P: IsA<Widget>>(&self, child: &P, view: &'static str) {
+        self.curr_view.replace(view);
+        self.overlay.set_child(Some(child));
     }
 
-    fn get_groups(self: Rc<App>, groups: SignaldTypes) -> Vec<Rc<conversation::Conversation>> {
-        if let SignaldTypes::GroupListV1(groups) = groups {
-            groups.groups.unwrap().drain(..).filter_map(|group| {
-                let db = self.db.lock().unwrap();
-                conversation::Conversation::new_group(group, &db).map(|conv| {
-                    Rc::new(conv)
-                })
-            }).collect()
-        } else {
-            panic!("Wrong type");
-        }
+    pub fn get_name(self: Rc<App>, number: &str) -> Option<String> {
+        self.client.get_name(number)
     }
 }
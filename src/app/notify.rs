@@ -0,0 +1,154 @@
+use gtk::prelude::*;
+use gtk::gio;
+use gtk::glib::{clone, MainContext};
+use std::rc::Rc;
+
+use signal_rs_core::conversation::Conversation;
+use signal_rs_core::models::NewMessage;
+
+use crate::app::App;
+
+impl App {
+    /// Register the actions desktop notifications trigger. Each takes the
+    /// account and the conversation's key as its target.
+    pub fn add_notification_actions(self: Rc<App>) {
+        let open = gio::SimpleAction::new("open-conversation", Some(&<(String, String)>::static_variant_type()));
+        open.connect_activate(clone!(@strong self as app => move |_, target| {
+            let target = target.and_then(|target| target.get::<(String, String)>());
+
+            MainContext::default().spawn_local(clone!(@strong app => async move {
+                if let Some(conversation) = app.clone().notified_conversation(target).await {
+                    app.clone().open_from_notification(conversation);
+                }
+            }));
+        }));
+
+        // Notifications can't take text input, so replying opens the
+        // conversation ready to type
+        let reply = gio::SimpleAction::new("reply-conversation", Some(&<(String, String)>::static_variant_type()));
+        reply.connect_activate(clone!(@strong self as app => move |_, target| {
+            let target = target.and_then(|target| target.get::<(String, String)>());
+
+            MainContext::default().spawn_local(clone!(@strong app => async move {
+                if let Some(conversation) = app.clone().notified_conversation(target).await {
+                    app.clone().open_from_notification(conversation);
+
+                    if let Some(active) = &*app.active.borrow() {
+                        active.entry.grab_focus();
+                    }
+                }
+            }));
+        }));
+
+        let mark_read = gio::SimpleAction::new("mark-read", Some(&<(String, String)>::static_variant_type()));
+        mark_read.connect_activate(clone!(@strong self as app => move |_, target| {
+            let target = target.and_then(|target| target.get::<(String, String)>());
+
+            MainContext::default().spawn_local(clone!(@strong app => async move {
+                if let Some(conversation) = app.clone().notified_conversation(target).await {
+                    app.clone().read_messages(conversation).await;
+
+                    // Clear the unread badge
+                    if app.curr_view.borrow().eq("main_view") {
+                        app.update_ui(&app.clone().main_view_ui(), "main_view");
+                    }
+                }
+            }));
+        }));
+
+        self.application.add_action(&open);
+        self.application.add_action(&reply);
+        self.application.add_action(&mark_read);
+    }
+
+    /// Show a desktop notification for `msg`, replacing any earlier one for
+    /// the same conversation, unless it's muted or already on screen
+    pub fn notify(self: Rc<App>, conversation: &Conversation, msg: &NewMessage) {
+        if msg.from_me || *conversation.muted.borrow() {
+            return;
+        }
+
+        if self.window.is_active() && *conversation.is_active.borrow() {
+            return;
+        }
+
+        let mut preview = preview(msg);
+
+        if conversation.groupid.is_some() {
+            let number = msg.number.clone().unwrap_or_default();
+            let sender = self.clone().get_name(&number).unwrap_or(number);
+            preview = format!("{}: {}", sender, preview);
+        }
+
+        let new_msgs = *conversation.new_msgs.borrow();
+        if new_msgs > 1 {
+            preview = format!("{}\n+{} more", preview, new_msgs - 1);
+        }
+
+        self.send_notification(&conversation.account, conversation.key(), conversation.get_name(), &preview);
+    }
+
+    /// Show a desktop notification for a message to an account other than
+    /// the active one. Its conversations aren't loaded, so it's named after
+    /// the sender and the account.
+    pub fn notify_other_account(self: Rc<App>, msg: &NewMessage) {
+        let key = msg.groupid.clone().or_else(|| msg.number.clone()).unwrap_or_default();
+
+        if msg.from_me || self.client.is_muted(&msg.account, &key) {
+            return;
+        }
+
+        let number = msg.number.clone().unwrap_or_default();
+        let sender = self.clone().get_name(&number).unwrap_or(number);
+        let title = format!("{} (to {})", sender, msg.account);
+
+        self.send_notification(&msg.account, &key, &title, &preview(msg));
+    }
+
+    pub fn withdraw_notification(&self, conversation: &Conversation) {
+        self.application.withdraw_notification(&notification_id(&conversation.account, conversation.key()));
+    }
+
+    fn send_notification(&self, account: &str, key: &str, title: &str, body: &str) {
+        let target = (account.to_owned(), key.to_owned()).to_variant();
+        let notification = gio::Notification::new(title);
+        notification.set_body(Some(body));
+        notification.set_default_action_and_target_value("app.open-conversation", Some(&target));
+        notification.add_button_with_target_value("Reply", "app.reply-conversation", Some(&target));
+        notification.add_button_with_target_value("Mark as read", "app.mark-read", Some(&target));
+
+        self.application.send_notification(Some(&notification_id(account, key)), &notification);
+    }
+
+    // The notification may be for another account, so switch to that first
+    async fn notified_conversation(self: Rc<App>, target: Option<(String, String)>) -> Option<Rc<Conversation>> {
+        let (account, key) = target?;
+
+        if account != self.client.account() {
+            self.clone().switch_account(account.clone()).await;
+        }
+
+        self.client.conversation(&key).filter(|conversation| conversation.account == account)
+    }
+
+    fn open_from_notification(self: Rc<App>, conversation: Rc<Conversation>) {
+        if let Some(active) = &*self.active.borrow() {
+            active.conversation.is_active.replace(false);
+        }
+
+        self.clone().conversation_ui(conversation);
+        self.window.present();
+    }
+}
+
+fn preview(msg: &NewMessage) -> String {
+    if msg.body.is_empty() && msg.attachments.is_some() {
+        "Sent an attachment".to_owned()
+    } else {
+        msg.body.clone()
+    }
+}
+
+fn notification_id(account: &str, key: &str) -> String {
+    format!("conversation-{}-{}", account, key)
+}
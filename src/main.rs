@@ -1,7 +1,3 @@
-#[macro_use]
-extern crate diesel;
-extern crate dotenv;
-
 use gtk::prelude::*;
 
 use gtk::gdk::Display;
@@ -9,16 +5,14 @@ use gtk::{
     Application, CssProvider, StyleContext, STYLE_PROVIDER_PRIORITY_APPLICATION
 };
 
-mod signald_bridge;
 mod app;
-mod database;
-mod schema;
-mod models;
-mod signal_type_utils;
 
 use crate::app::App;
 
 fn main() {
+    // Set RUST_LOG to see what's going on with signald
+    env_logger::init();
+
     let application = Application::new(Some("com.github.zschira.signalrs"), Default::default());
     application.connect_startup(|app| {
         // The CSS "magic" happens here.